@@ -1,821 +1,527 @@
-/// (C) Copyright 2020 Shawfeng Dong. All rights reserved.
-/// Use of this source code is governed by an MIT-style
-/// license that can be found in the LICENSE file.
+// (C) Copyright 2020 Shawfeng Dong. All rights reserved.
+// Use of this source code is governed by an MIT-style
+// license that can be found in the LICENSE file.
 
-/// NASDAQ ITCH 5.0 parser
-/// Reference: Nasdaq TotalView-ITCH 5.0 Specification
+// NASDAQ ITCH 5.0 parser
+// Reference: Nasdaq TotalView-ITCH 5.0 Specification
+// The decoding and the CSV output live in the itch5 library (src/); this
+// binary only wires them to the command line.
 
 use std::path::Path;
 use std::fs;
-use std::io::prelude::*;
-use std::convert::TryInto;
+use std::io;
 use std::time::SystemTime;
-// use std::collections::HashMap;
 
-fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    let argc = args.len();
-    if argc<3 || argc>4 {
-        eprintln!("Usage: {} input_file_path output_folder_path [msg_types]\n", args[0]);
-        eprintln!("If msg_types is not provided, output will be generated for all types");
-        std::process::exit(1);
-    }
-    
-    // 22 message types in ITCH 5.0 specification
-    let msg_type = ['S', 'R', 'H', 'Y', 'L', 'V', 'W', 'K', 'J',
-        'h', 'A', 'F', 'E', 'C', 'X', 'D', 'U', 'P', 'Q', 'B', 'I', 'N'];
+use itch5::{
+    ArrowSink, BarsSink, BboSink, Compression, CsvSink, FrameSource, InterleavedCsvSink,
+    ItchFrameReader, JsonlSink, MappedFile, OutputSink, ParquetSink, Parser, PartitionedSink,
+    Partitioning, SnapshotSink, SqliteSink, TableFormat, TradesSink, Trigger, MESSAGE_TYPES,
+};
+
+// Commands that derive data from the order book rather than writing out
+// the messages; given as the first positional argument.
+const COMMANDS: &[&str] = &["snapshot", "bbo", "trades", "bars"];
+
+fn usage(prog: &str) -> ! {
+    eprintln!("Usage: {} [options] input_file_path output_folder_path [msg_types]", prog);
+    eprintln!("       {} command [options] input_file_path output_folder_path\n", prog);
+    eprintln!("If msg_types is not provided, output will be generated for all types");
+    eprintln!("If input_file_path is -, the ITCH stream is read from stdin");
+    eprintln!("With --format sqlite, output_folder_path is the database file instead\n");
+    eprintln!("Commands write data derived from the order book instead of the messages:");
+    eprintln!("  snapshot     depth snapshots of the order book");
+    eprintln!("  bbo          every change of the best bid or offer");
+    eprintln!("  trades       the trade tape");
+    eprintln!("  bars         OHLCV bars of the trade tape\n");
+    eprintln!("Options:");
+    eprintln!("  --format FORMAT");
+    eprintln!("               csv (default), parquet or arrow (Feather): one file per");
+    eprintln!("               message type; jsonl: all messages in feed order in one file;");
+    eprintln!("               sqlite: one table per message type; commands take csv or");
+    eprintln!("               parquet");
+    eprintln!("  --mmap       memory-map the input file instead of reading it");
+    eprintln!("  --name NAME  base name of the output files (required when reading stdin)");
+    eprintln!("  --buffer-size BYTES");
+    eprintln!("               write buffer size of each output file (default {}, or {} with",
+              itch5::sink::DEFAULT_WRITE_BUFFER, itch5::sink::PARTITION_WRITE_BUFFER);
+    eprintln!("               --partition-by)");
+    eprintln!("  --no-header  don't write a header row at the top of each CSV file");
+    eprintln!("  --interleave write one CSV file in feed order, with the seq and byte offset");
+    eprintln!("               of each message, instead of one file per message type");
+    eprintln!("  --enrich     add the stock, side and price of the order to E, C, X, D and");
+    eprintln!("               U rows, and the stock to B rows, in the CSV files; with");
+    eprintln!("               --from, only along with --keep-state");
+    eprintln!("  --row-group-size ROWS");
+    eprintln!("               rows per Parquet row group (default {})",
+              itch5::sink::DEFAULT_ROW_GROUP_SIZE);
+    eprintln!("  --symbols LIST");
+    eprintln!("               only keep the messages of these comma-separated symbols, e.g.");
+    eprintln!("               AAPL,MSFT, and market-wide messages; case does not matter");
+    eprintln!("  --symbols-file FILE");
+    eprintln!("               same, with the symbols read from FILE, one per line");
+    eprintln!("  --from TIME  skip messages before TIME, e.g. 09:30:00 or 09:30:00.000000001");
+    eprintln!("  --to TIME    skip messages at or after TIME, e.g. 16:00:00");
+    eprintln!("  --keep-state keep the R, H and order messages from before --from, so that");
+    eprintln!("               order books built from the output are correct; not for");
+    eprintln!("               commands, which always read the whole input and only write");
+    eprintln!("               the rows between --from and --to");
+    eprintln!("  --partition-by symbol|locate-bucket");
+    eprintln!("               write csv or parquet files to one subfolder per symbol, e.g.");
+    eprintln!("               AAPL/, or per bucket of stock locate codes, e.g. bucket-07/;");
+    eprintln!("               market-wide messages stay in output_folder_path; the csv");
+    eprintln!("               files of up to {} subfolders are open at once, while parquet",
+              itch5::sink::DEFAULT_OPEN_PARTITIONS);
+    eprintln!("               keeps them all open, so symbol then needs --symbols");
+    eprintln!("  --buckets N  number of buckets of locate-bucket (default {})",
+              itch5::sink::DEFAULT_BUCKETS);
+    eprintln!("\nSnapshot and bars options:");
+    eprintln!("  --depth LEVELS");
+    eprintln!("               price levels per side (default {})", itch5::sink::DEFAULT_DEPTH);
+    eprintln!("  --interval DURATION");
+    eprintln!("               ITCH time between snapshots, or the length of a bar, e.g. 100ms,");
+    eprintln!("               1s or 5m (default 100ms for snapshots, 1m for bars)");
+    eprintln!("  --on-change  take a snapshot after every change to a book instead");
+    std::process::exit(1);
+}
 
-    // Set flags to process specific message types. If third (optional)
-    // command line argument is not provided, assumes that all messages types
-    // will be parsed
-    let mut parse_flag: [bool; 128]  = [false; 128];
-    if argc == 3 {
-        for t in &msg_type {
-            parse_flag[*t as usize] = true;
-        }
-    } else {
-        for c in args[3].chars() {
-            let mut found = false;
-            for t in &msg_type {
-                if c == *t {
-                    parse_flag[c as usize] = true;
-                    found = true;
-                    break;
+// Parses a duration such as 500us, 100ms, 1s, 5m or 1h into nanoseconds.
+fn parse_duration(s: &str) -> Option<u64> {
+    let split = s.find(|c: char| !c.is_ascii_digit())?;
+    let n: u64 = s[..split].parse().ok()?;
+    let unit = match &s[split..] {
+        "ns" => 1,
+        "us" => 1_000,
+        "ms" => 1_000_000,
+        "s" => 1_000_000_000,
+        "m" => 60_000_000_000,
+        "h" => 3_600_000_000_000,
+        _ => return None,
+    };
+    n.checked_mul(unit).filter(|&ns| ns > 0)
+}
+
+// Parses a time of day such as 09:30, 09:30:00 or 09:30:00.123456789 into
+// nanoseconds since midnight.
+fn parse_time(s: &str) -> Option<u64> {
+    let digits = |f: &str| !f.is_empty() && f.bytes().all(|b| b.is_ascii_digit());
+    let (hms, fraction) = match s.split_once('.') {
+        Some((hms, fraction)) if digits(fraction) && fraction.len() <= 9 => (hms, fraction),
+        Some(_) => return None,
+        None => (s, ""),
+    };
+    let fields: Vec<u64> = hms
+        .split(':')
+        .map(|f| if f.len() == 2 && digits(f) { f.parse().ok() } else { None })
+        .collect::<Option<_>>()?;
+    let seconds = match fields[..] {
+        [h, m] if fraction.is_empty() && h <= 24 && m < 60 => h * 3600 + m * 60,
+        [h, m, sec] if h <= 24 && m < 60 && sec < 60 => h * 3600 + m * 60 + sec,
+        _ => return None,
+    };
+    let nanos = match fraction {
+        "" => 0,
+        _ => fraction.parse::<u64>().ok()? * 10u64.pow(9 - fraction.len() as u32),
+    };
+    // 24:00 is the end of the day; nothing after it
+    Some(seconds * 1_000_000_000 + nanos).filter(|&t| t <= 24 * 3_600_000_000_000)
+}
+
+// Reports a fatal error and exits.
+fn die(what: &str, e: impl std::fmt::Display) -> ! {
+    eprintln!("{}: {}", what, e);
+    std::process::exit(1);
+}
+
+fn main() {
+    // options start with "--"; everything else is positional
+    let mut argv = std::env::args();
+    let prog = argv.next().unwrap_or_else(|| "parse_itch5".to_string());
+    let mut use_mmap = false;
+    let mut out_name: Option<String> = None;
+    let mut buffer_size: Option<usize> = None;
+    let mut header = true;
+    let mut interleave = false;
+    let mut enrich = false;
+    let mut format = String::from("csv");
+    let mut row_group_size = itch5::sink::DEFAULT_ROW_GROUP_SIZE;
+    let mut depth = itch5::sink::DEFAULT_DEPTH;
+    let mut interval: Option<u64> = None;
+    let mut on_change = false;
+    let mut symbols: Option<Vec<String>> = None;
+    let mut symbols_file: Option<String> = None;
+    let mut from: Option<u64> = None;
+    let mut to: Option<u64> = None;
+    let mut keep_state = false;
+    let mut partition_by: Option<String> = None;
+    let mut buckets: Option<u16> = None;
+    let mut args: Vec<String> = vec![prog.clone()];
+    while let Some(arg) = argv.next() {
+        match arg.as_str() {
+            "--mmap" => use_mmap = true,
+            "--no-header" => header = false,
+            "--interleave" => interleave = true,
+            "--enrich" => enrich = true,
+            "--name" => out_name = Some(argv.next().unwrap_or_else(|| usage(&prog))),
+            "--buffer-size" => {
+                buffer_size = match argv.next().map(|v| v.parse()) {
+                    Some(Ok(n)) if n > 0 => Some(n),
+                    _ => usage(&prog),
                 }
             }
-            if !found {
-                eprintln!("{} is not a valid message type", c);
-                eprintln!("Valid ITCH v5.0 message types are:");
-                eprintln!("S R H Y L V W K J h A F E C X D U P Q B I N");
-                std::process::exit(1);
+            "--format" => {
+                format = argv.next().unwrap_or_else(|| usage(&prog));
+                if !["csv", "parquet", "arrow", "jsonl", "sqlite"].contains(&format.as_str()) {
+                    eprintln!("{} is not a valid output format", format);
+                    usage(&prog);
+                }
+            }
+            "--row-group-size" => {
+                row_group_size = match argv.next().map(|v| v.parse()) {
+                    Some(Ok(n)) if n > 0 => n,
+                    _ => usage(&prog),
+                }
+            }
+            "--depth" => {
+                depth = match argv.next().map(|v| v.parse()) {
+                    Some(Ok(n)) if n > 0 => n,
+                    _ => usage(&prog),
+                }
+            }
+            "--interval" => {
+                interval = match argv.next().as_deref().and_then(parse_duration) {
+                    Some(ns) => Some(ns),
+                    None => usage(&prog),
+                }
+            }
+            "--on-change" => on_change = true,
+            "--symbols" => {
+                let list = argv.next().unwrap_or_else(|| usage(&prog));
+                symbols = Some(
+                    list.split(',').filter(|s| !s.is_empty()).map(String::from).collect(),
+                );
+            }
+            "--from" => {
+                from = match argv.next().as_deref().and_then(parse_time) {
+                    Some(ns) => Some(ns),
+                    None => usage(&prog),
+                }
+            }
+            "--to" => {
+                to = match argv.next().as_deref().and_then(parse_time) {
+                    Some(ns) => Some(ns),
+                    None => usage(&prog),
+                }
+            }
+            "--keep-state" => keep_state = true,
+            "--partition-by" => {
+                partition_by = argv.next();
+                if !matches!(partition_by.as_deref(), Some("symbol") | Some("locate-bucket")) {
+                    usage(&prog);
+                }
             }
+            "--buckets" => {
+                buckets = match argv.next().map(|v| v.parse()) {
+                    Some(Ok(n)) if n > 0 => Some(n),
+                    _ => usage(&prog),
+                }
+            }
+            "--symbols-file" => symbols_file = Some(argv.next().unwrap_or_else(|| usage(&prog))),
+            _ if arg.starts_with("--") => {
+                eprintln!("{} is not a valid option", arg);
+                usage(&prog);
+            }
+            _ => args.push(arg),
         }
     }
-
-	// args[1]: input file path
-    let mut in_file = fs::File::open(&args[1]).expect("Can't open the input file!");
-
-	// args[2]: output folder path
-    fs::create_dir_all(&args[2]).expect("Can't create the output folder!");
-
-    // total number of all messages
-    let mut total = 0u32;
-    // total number of messages for each message type
-    let mut total_type = [0u32; 22];
-
-	println!("=========== Parsing ITCH v5.0 starts ===========");
-	println!("Input file: {}", args[1]);
-	println!("Output folder: {}", args[2]);
-
-    let start = SystemTime::now();
-    
-    // open files only for specified message types
-    let out_base = Path::new(&args[1]).file_stem().unwrap().to_str().unwrap();
-    let mut csv_full_path: String;
-
-    // This is ugly as hell!!! 
-    // But I can't get arrays of FILEs, nor HashMap of FILEs, to work yet
-    // let mut csv_file: [fs::File; 22];
-    // let mut csv_file = HashMap::new();
-
-    // rust scope!!!
-    let mut t = msg_type[0];
-    if t == 'h' {
-        // workaround of the limitation of case-insensitive filesystems
-        // both 'H' and 'h' are valid mesaage types
-        csv_full_path = format!("{}/{}-halt.csv", args[2], out_base);
-    } else {
-        csv_full_path = format!("{}/{}-{}.csv", args[2], out_base, t);
-    }
-    println!("Output file: {}", csv_full_path);
-    let mut csv_file_0 = fs::File::create(csv_full_path).expect("Can't create the csv output file!");
-    
-    t = msg_type[1];
-    if t == 'h' {
-        // workaround of the limitation of case-insensitive filesystems
-        // both 'H' and 'h' are valid mesaage types
-        csv_full_path = format!("{}/{}-halt.csv", args[2], out_base);
-    } else {
-        csv_full_path = format!("{}/{}-{}.csv", args[2], out_base, t);
+    let command = match args.get(1) {
+        Some(arg) if COMMANDS.contains(&arg.as_str()) => Some(args.remove(1)),
+        _ => None,
+    };
+    let argc = args.len();
+    if !(3..=4).contains(&argc) || (command.is_some() && argc != 3) {
+        usage(&prog);
     }
-    println!("Output file: {}", csv_full_path);
-    let mut csv_file_1 = fs::File::create(csv_full_path).expect("Can't create the csv output file!");
-
-    t = msg_type[2];
-    if t == 'h' {
-        // workaround of the limitation of case-insensitive filesystems
-        // both 'H' and 'h' are valid mesaage types
-        csv_full_path = format!("{}/{}-halt.csv", args[2], out_base);
-    } else {
-        csv_full_path = format!("{}/{}-{}.csv", args[2], out_base, t);
+    let from_stdin = args[1] == "-";
+    if from_stdin && use_mmap {
+        eprintln!("--mmap can't be used when reading stdin");
+        std::process::exit(1);
     }
-    println!("Output file: {}", csv_full_path);
-    let mut csv_file_2 = fs::File::create(csv_full_path).expect("Can't create the csv output file!");
-
-    t = msg_type[3];
-    if t == 'h' {
-        // workaround of the limitation of case-insensitive filesystems
-        // both 'H' and 'h' are valid mesaage types
-        csv_full_path = format!("{}/{}-halt.csv", args[2], out_base);
-    } else {
-        csv_full_path = format!("{}/{}-{}.csv", args[2], out_base, t);
+    if interleave && format != "csv" {
+        eprintln!("--interleave only applies to csv output");
+        std::process::exit(1);
     }
-    println!("Output file: {}", csv_full_path);
-    let mut csv_file_3 = fs::File::create(csv_full_path).expect("Can't create the csv output file!");
-
-    t = msg_type[4];
-    if t == 'h' {
-        // workaround of the limitation of case-insensitive filesystems
-        // both 'H' and 'h' are valid mesaage types
-        csv_full_path = format!("{}/{}-halt.csv", args[2], out_base);
-    } else {
-        csv_full_path = format!("{}/{}-{}.csv", args[2], out_base, t);
+    if enrich && (format != "csv" || interleave || command.is_some()) {
+        eprintln!("--enrich only applies to csv output with one file per message type");
+        std::process::exit(1);
     }
-    println!("Output file: {}", csv_full_path);
-    let mut csv_file_4 = fs::File::create(csv_full_path).expect("Can't create the csv output file!");
-
-    t = msg_type[5];
-    if t == 'h' {
-        // workaround of the limitation of case-insensitive filesystems
-        // both 'H' and 'h' are valid mesaage types
-        csv_full_path = format!("{}/{}-halt.csv", args[2], out_base);
-    } else {
-        csv_full_path = format!("{}/{}-{}.csv", args[2], out_base, t);
+    if command.is_some() && !["csv", "parquet"].contains(&format.as_str()) {
+        eprintln!("{} output only supports csv and parquet", command.as_deref().unwrap_or(""));
+        std::process::exit(1);
     }
-    println!("Output file: {}", csv_full_path);
-    let mut csv_file_5 = fs::File::create(csv_full_path).expect("Can't create the csv output file!");
-
-    t = msg_type[6];
-    if t == 'h' {
-        // workaround of the limitation of case-insensitive filesystems
-        // both 'H' and 'h' are valid mesaage types
-        csv_full_path = format!("{}/{}-halt.csv", args[2], out_base);
-    } else {
-        csv_full_path = format!("{}/{}-{}.csv", args[2], out_base, t);
+    let partitioning = match partition_by.as_deref() {
+        Some("symbol") => Some(Partitioning::Symbol),
+        Some(_) => {
+            Some(Partitioning::LocateBucket(buckets.unwrap_or(itch5::sink::DEFAULT_BUCKETS)))
+        }
+        None => None,
+    };
+    if partitioning.is_some()
+        && (!["csv", "parquet"].contains(&format.as_str()) || interleave || command.is_some())
+    {
+        eprintln!("--partition-by only applies to csv and parquet output with one file per");
+        eprintln!("message type");
+        std::process::exit(1);
     }
-    println!("Output file: {}", csv_full_path);
-    let mut csv_file_6 = fs::File::create(csv_full_path).expect("Can't create the csv output file!");
-
-    t = msg_type[7];
-    if t == 'h' {
-        // workaround of the limitation of case-insensitive filesystems
-        // both 'H' and 'h' are valid mesaage types
-        csv_full_path = format!("{}/{}-halt.csv", args[2], out_base);
-    } else {
-        csv_full_path = format!("{}/{}-{}.csv", args[2], out_base, t);
+    // a folder per stock of the whole market would exhaust the open files
+    if partitioning == Some(Partitioning::Symbol)
+        && format == "parquet"
+        && symbols.is_none()
+        && symbols_file.is_none()
+    {
+        eprintln!("--partition-by symbol with parquet output needs --symbols or --symbols-file;");
+        eprintln!("use --partition-by locate-bucket for the whole market");
+        std::process::exit(1);
     }
-    println!("Output file: {}", csv_full_path);
-    let mut csv_file_7 = fs::File::create(csv_full_path).expect("Can't create the csv output file!");
-
-    t = msg_type[8];
-    if t == 'h' {
-        // workaround of the limitation of case-insensitive filesystems
-        // both 'H' and 'h' are valid mesaage types
-        csv_full_path = format!("{}/{}-halt.csv", args[2], out_base);
-    } else {
-        csv_full_path = format!("{}/{}-{}.csv", args[2], out_base, t);
+    if buckets.is_some() && partition_by.as_deref() != Some("locate-bucket") {
+        eprintln!("--buckets only applies with --partition-by locate-bucket");
+        std::process::exit(1);
     }
-    println!("Output file: {}", csv_full_path);
-    let mut csv_file_8 = fs::File::create(csv_full_path).expect("Can't create the csv output file!");
-
-    t = msg_type[9];
-    if t == 'h' {
-        // workaround of the limitation of case-insensitive filesystems
-        // both 'H' and 'h' are valid mesaage types
-        csv_full_path = format!("{}/{}-halt.csv", args[2], out_base);
-    } else {
-        csv_full_path = format!("{}/{}-{}.csv", args[2], out_base, t);
+    // partitions open many more files, each with a buffer of its own
+    let buffer_size = buffer_size.unwrap_or(match partitioning {
+        Some(_) => itch5::sink::PARTITION_WRITE_BUFFER,
+        None => itch5::sink::DEFAULT_WRITE_BUFFER,
+    });
+    if let (Some(from), Some(to)) = (from, to) {
+        if from >= to {
+            eprintln!("--from must be earlier than --to");
+            std::process::exit(1);
+        }
     }
-    println!("Output file: {}", csv_full_path);
-    let mut csv_file_9 = fs::File::create(csv_full_path).expect("Can't create the csv output file!");
-
-    t = msg_type[10];
-    if t == 'h' {
-        // workaround of the limitation of case-insensitive filesystems
-        // both 'H' and 'h' are valid mesaage types
-        csv_full_path = format!("{}/{}-halt.csv", args[2], out_base);
-    } else {
-        csv_full_path = format!("{}/{}-{}.csv", args[2], out_base, t);
+    if keep_state && from.is_none() {
+        eprintln!("--keep-state only applies with --from");
+        std::process::exit(1);
     }
-    println!("Output file: {}", csv_full_path);
-    let mut csv_file_10 = fs::File::create(csv_full_path).expect("Can't create the csv output file!");
-
-    t = msg_type[11];
-    if t == 'h' {
-        // workaround of the limitation of case-insensitive filesystems
-        // both 'H' and 'h' are valid mesaage types
-        csv_full_path = format!("{}/{}-halt.csv", args[2], out_base);
-    } else {
-        csv_full_path = format!("{}/{}-{}.csv", args[2], out_base, t);
+    // the orders resting at --from are added before it
+    if enrich && from.is_some() && !keep_state {
+        eprintln!("--enrich with --from needs --keep-state to look up the orders added earlier");
+        std::process::exit(1);
     }
-    println!("Output file: {}", csv_full_path);
-    let mut csv_file_11 = fs::File::create(csv_full_path).expect("Can't create the csv output file!");
-
-    t = msg_type[12];
-    if t == 'h' {
-        // workaround of the limitation of case-insensitive filesystems
-        // both 'H' and 'h' are valid mesaage types
-        csv_full_path = format!("{}/{}-halt.csv", args[2], out_base);
-    } else {
-        csv_full_path = format!("{}/{}-{}.csv", args[2], out_base, t);
+    if keep_state && command.is_some() {
+        eprintln!("--keep-state doesn't apply to commands, which always read the whole input");
+        std::process::exit(1);
     }
-    println!("Output file: {}", csv_full_path);
-    let mut csv_file_12 = fs::File::create(csv_full_path).expect("Can't create the csv output file!");
-
-    t = msg_type[13];
-    if t == 'h' {
-        // workaround of the limitation of case-insensitive filesystems
-        // both 'H' and 'h' are valid mesaage types
-        csv_full_path = format!("{}/{}-halt.csv", args[2], out_base);
-    } else {
-        csv_full_path = format!("{}/{}-{}.csv", args[2], out_base, t);
+    if from_stdin && out_name.is_none() {
+        eprintln!("--name is required when reading stdin");
+        std::process::exit(1);
     }
-    println!("Output file: {}", csv_full_path);
-    let mut csv_file_13 = fs::File::create(csv_full_path).expect("Can't create the csv output file!");
-
-    t = msg_type[14];
-    if t == 'h' {
-        // workaround of the limitation of case-insensitive filesystems
-        // both 'H' and 'h' are valid mesaage types
-        csv_full_path = format!("{}/{}-halt.csv", args[2], out_base);
-    } else {
-        csv_full_path = format!("{}/{}-{}.csv", args[2], out_base, t);
+    
+    // one symbol per line; blank lines are ignored
+    if let Some(file) = symbols_file {
+        let list = fs::read_to_string(&file)
+            .unwrap_or_else(|e| die(&format!("Can't read {}", file), e));
+        symbols.get_or_insert_with(Vec::new).extend(list.split_whitespace().map(String::from));
     }
-    println!("Output file: {}", csv_full_path);
-    let mut csv_file_14 = fs::File::create(csv_full_path).expect("Can't create the csv output file!");
 
-    t = msg_type[15];
-    if t == 'h' {
-        // workaround of the limitation of case-insensitive filesystems
-        // both 'H' and 'h' are valid mesaage types
-        csv_full_path = format!("{}/{}-halt.csv", args[2], out_base);
+    // Select the message types to parse. If third (optional) command line
+    // argument is not provided, assumes that all messages types will be
+    // parsed
+    let types: Vec<u8> = if matches!(command.as_deref(), Some("trades") | Some("bars")) {
+        // the trades, the order messages to price them, and the stock
+        // directory for the symbols
+        b"RAFECXDUPQB".to_vec()
+    } else if command.is_some() {
+        // the order messages, and the stock directory for the symbols
+        b"RAFECXDU".to_vec()
+    } else if argc == 3 {
+        MESSAGE_TYPES.to_vec()
     } else {
-        csv_full_path = format!("{}/{}-{}.csv", args[2], out_base, t);
-    }
-    println!("Output file: {}", csv_full_path);
-    let mut csv_file_15 = fs::File::create(csv_full_path).expect("Can't create the csv output file!");
+        for c in args[3].chars() {
+            if !c.is_ascii() || itch5::message_type_index(c as u8).is_none() {
+                eprintln!("{} is not a valid message type", c);
+                eprintln!("Valid ITCH v5.0 message types are:");
+                eprintln!("S R H Y L V W K J h A F E C X D U P Q B I N");
+                std::process::exit(1);
+            }
+        }
+        args[3].bytes().collect()
+    };
 
-    t = msg_type[16];
-    if t == 'h' {
-        // workaround of the limitation of case-insensitive filesystems
-        // both 'H' and 'h' are valid mesaage types
-        csv_full_path = format!("{}/{}-halt.csv", args[2], out_base);
+	// args[1]: input file path
+    // each frame is a 2-byte big-endian length followed by the message
+    let mapped_file;
+    let mut frames: Box<dyn FrameSource> = if use_mmap {
+        mapped_file = MappedFile::open(&args[1])
+            .unwrap_or_else(|e| die("Can't map the input file", e));
+        if Compression::from_magic(mapped_file.as_bytes()) != Compression::None {
+            eprintln!("--mmap can't be used with a compressed input file");
+            std::process::exit(1);
+        }
+        Box::new(mapped_file.frames())
+    } else if from_stdin {
+        let in_file = itch5::decompress(io::stdin(), Compression::None)
+            .unwrap_or_else(|e| die("Can't read from stdin", e));
+        Box::new(ItchFrameReader::new(in_file))
     } else {
-        csv_full_path = format!("{}/{}-{}.csv", args[2], out_base, t);
+        // gzip, zstd and bzip2 files are decompressed on the fly
+        let in_file = itch5::open_input(&args[1])
+            .unwrap_or_else(|e| die("Can't open the input file", e));
+        Box::new(ItchFrameReader::new(in_file))
+    };
+
+	// args[2]: output folder path, or the database file for sqlite
+    let out_folder = match format.as_str() {
+        "sqlite" => Path::new(&args[2]).parent().unwrap_or(Path::new("")),
+        _ => Path::new(&args[2]),
+    };
+    if !out_folder.as_os_str().is_empty() {
+        fs::create_dir_all(out_folder).unwrap_or_else(|e| die("Can't create the output folder", e));
     }
-    println!("Output file: {}", csv_full_path);
-    let mut csv_file_16 = fs::File::create(csv_full_path).expect("Can't create the csv output file!");
 
-    t = msg_type[17];
-    if t == 'h' {
-        // workaround of the limitation of case-insensitive filesystems
-        // both 'H' and 'h' are valid mesaage types
-        csv_full_path = format!("{}/{}-halt.csv", args[2], out_base);
-    } else {
-        csv_full_path = format!("{}/{}-{}.csv", args[2], out_base, t);
-    }
-    println!("Output file: {}", csv_full_path);
-    let mut csv_file_17 = fs::File::create(csv_full_path).expect("Can't create the csv output file!");
+	println!("=========== Parsing ITCH v5.0 starts ===========");
+	println!("Input file: {}", if from_stdin { "(stdin)" } else { &args[1] });
+	if format == "sqlite" {
+		println!("Output database: {}", args[2]);
+	} else {
+		println!("Output folder: {}", args[2]);
+	}
 
-    t = msg_type[18];
-    if t == 'h' {
-        // workaround of the limitation of case-insensitive filesystems
-        // both 'H' and 'h' are valid mesaage types
-        csv_full_path = format!("{}/{}-halt.csv", args[2], out_base);
-    } else {
-        csv_full_path = format!("{}/{}-{}.csv", args[2], out_base, t);
+    let start = SystemTime::now();
+    
+    // a compression suffix such as .gz is not part of the base name
+    let out_base = match out_name {
+        Some(name) => name,
+        None => itch5::output_stem(Path::new(&args[1])),
+    };
+
+    // output files are created on first use, and only for the message
+    // types selected on the command line
+    let table_format = match format.as_str() {
+        "parquet" => TableFormat::Parquet,
+        _ => TableFormat::Csv,
+    };
+    // commands build the book from the whole input and apply the time
+    // window to the rows they write
+    let (window_start, window_end) = (from.unwrap_or(0), to.unwrap_or(u64::MAX));
+    let mut sink: Box<dyn OutputSink> = match format.as_str() {
+        _ if partitioning.is_some() => {
+            let partitioning = partitioning.unwrap();
+            let make = |folder: &Path| -> Box<dyn OutputSink> {
+                match table_format {
+                    TableFormat::Parquet => Box::new(
+                        ParquetSink::new(folder, out_base.as_str())
+                            .row_group_size(row_group_size)
+                            .buffer_size(buffer_size),
+                    ),
+                    TableFormat::Csv => Box::new(
+                        CsvSink::new(folder, out_base.as_str())
+                            .buffer_size(buffer_size)
+                            .header(header)
+                            .only(&types)
+                            .enrich(enrich),
+                    ),
+                }
+            };
+            // R is needed for the symbols whether or not it is written; with
+            // --enrich, the CSV files filter the types themselves
+            let partitioned = PartitionedSink::new(&args[2], partitioning, make);
+            if enrich {
+                Box::new(partitioned)
+            } else {
+                Box::new(partitioned.only(&types))
+            }
+        }
+        _ if command.as_deref() == Some("bars") => {
+            let name = format!("{}-bars.{}", out_base, table_format.extension());
+            let bars = BarsSink::new(Path::new(&args[2]).join(name), table_format);
+            Box::new(
+                bars.interval(interval.unwrap_or(itch5::sink::DEFAULT_BAR_INTERVAL))
+                    .window(window_start, window_end),
+            )
+        }
+        _ if command.as_deref() == Some("trades") => {
+            let name = format!("{}-trades.{}", out_base, table_format.extension());
+            let trades = TradesSink::new(Path::new(&args[2]).join(name), table_format);
+            Box::new(trades.window(window_start, window_end))
+        }
+        _ if command.as_deref() == Some("bbo") => {
+            let name = format!("{}-bbo.{}", out_base, table_format.extension());
+            let bbo = BboSink::new(Path::new(&args[2]).join(name), table_format);
+            Box::new(bbo.window(window_start, window_end))
+        }
+        _ if command.as_deref() == Some("snapshot") => {
+            let name = format!("{}-snapshot.{}", out_base, table_format.extension());
+            let trigger = match interval {
+                _ if on_change => Trigger::OnChange,
+                Some(ns) => Trigger::Interval(ns),
+                None => Trigger::Interval(100_000_000),
+            };
+            Box::new(
+                SnapshotSink::new(Path::new(&args[2]).join(name), table_format)
+                    .depth(depth)
+                    .trigger(trigger)
+                    .window(window_start, window_end),
+            )
+        }
+        "parquet" => Box::new(
+            ParquetSink::new(&args[2], out_base)
+                .row_group_size(row_group_size)
+                .buffer_size(buffer_size),
+        ),
+        "arrow" => Box::new(ArrowSink::new(&args[2], out_base)),
+        "sqlite" => Box::new(
+            SqliteSink::create(&args[2]).unwrap_or_else(|e| die("Can't create the database", e)),
+        ),
+        "jsonl" => {
+            let path = Path::new(&args[2]).join(format!("{}.jsonl", out_base));
+            Box::new(JsonlSink::new(path).buffer_size(buffer_size))
+        }
+        _ if interleave => {
+            let path = Path::new(&args[2]).join(format!("{}.csv", out_base));
+            Box::new(
+                InterleavedCsvSink::new(path)
+                    .buffer_size(buffer_size)
+                    .header(header),
+            )
+        }
+        // written through buffers that are flushed at the end
+        _ => Box::new(
+            CsvSink::new(&args[2], out_base)
+                .buffer_size(buffer_size)
+                .header(header)
+                .only(&types)
+                .enrich(enrich),
+        ),
+    };
+    // enrichment looks up the orders and symbols of the types not written
+    let mut parsed = types.clone();
+    if enrich {
+        parsed.extend(b"RAFECXDU".iter().filter(|t| !types.contains(t)));
+    } else if partitioning == Some(Partitioning::Symbol) && !types.contains(&b'R') {
+        parsed.push(b'R');
     }
-    println!("Output file: {}", csv_full_path);
-    let mut csv_file_18 = fs::File::create(csv_full_path).expect("Can't create the csv output file!");
-
-    t = msg_type[19];
-    if t == 'h' {
-        // workaround of the limitation of case-insensitive filesystems
-        // both 'H' and 'h' are valid mesaage types
-        csv_full_path = format!("{}/{}-halt.csv", args[2], out_base);
-    } else {
-        csv_full_path = format!("{}/{}-{}.csv", args[2], out_base, t);
+    let mut parser = Parser::new().only(&parsed);
+    if let Some(symbols) = symbols {
+        parser = parser.symbols(symbols);
     }
-    println!("Output file: {}", csv_full_path);
-    let mut csv_file_19 = fs::File::create(csv_full_path).expect("Can't create the csv output file!");
-
-    t = msg_type[20];
-    if t == 'h' {
-        // workaround of the limitation of case-insensitive filesystems
-        // both 'H' and 'h' are valid mesaage types
-        csv_full_path = format!("{}/{}-halt.csv", args[2], out_base);
-    } else {
-        csv_full_path = format!("{}/{}-{}.csv", args[2], out_base, t);
+    if command.is_none() {
+        parser = parser.start_time(window_start).end_time(window_end).keep_state(keep_state);
     }
-    println!("Output file: {}", csv_full_path);
-    let mut csv_file_20 = fs::File::create(csv_full_path).expect("Can't create the csv output file!");
-
-    t = msg_type[21];
-    if t == 'h' {
-        // workaround of the limitation of case-insensitive filesystems
-        // both 'H' and 'h' are valid mesaage types
-        csv_full_path = format!("{}/{}-halt.csv", args[2], out_base);
-    } else {
-        csv_full_path = format!("{}/{}-{}.csv", args[2], out_base, t);
+    let result = parser.run(&mut *frames, &mut sink);
+    for path in sink.paths() {
+        println!("Output file: {}", path.display());
     }
-    println!("Output file: {}", csv_full_path);
-    let mut csv_file_21 = fs::File::create(csv_full_path).expect("Can't create the csv output file!");
-
-	// first two bytes before each message starts encodes the length of the message
-	let mut msg_header = [0; 2];
-    loop {
-        match in_file.read(&mut msg_header) {
-            Ok(n) => {
-                if n < 2 {
-                    println!("=========== Parsing ITCH v5.0 ends   ===========");
-                    break;
-                }
-                let msg_length = u16::from_be_bytes(msg_header);
-	            // println!("Message length: {}", msg_length);
-                // read msg_length bytes
-                let mut m = vec![0u8; msg_length.try_into().unwrap()];
-                match in_file.read_exact(&mut m) {
-                    Ok(_) => {
-                        // message type
-                        let t: char = m[0].into();
-                        // println!("Message length: {}, type = {}", msg_length, t);
-                        match t {
-                            'S' => {
-                                if parse_flag['S' as usize] {
-                                let stock_locate = u16::from_be_bytes([m[1], m[2]]);
-                                let tracking_number = u16::from_be_bytes([m[3], m[4]]);
-                                let timestamp = u64::from_be_bytes([0, 0, m[5], m[6],m[7],
-                                                                   m[8], m[9], m[10]]);
-                                let event_code: char = m[11].into();
-                                csv_file_0.write_fmt(format_args!("{},{},{},{}.{:09},{}\n", 
-                                         t, stock_locate, tracking_number, 
-                                         timestamp/1000000000, timestamp%1000000000, 
-                                         event_code)).expect("Can't write to csv file!");
-                                total_type[0] += 1;
-                                total += 1;
-                                }
-                            },
-                            'R' => {
-                                let stock_locate = u16::from_be_bytes([m[1], m[2]]);
-                                let tracking_number = u16::from_be_bytes([m[3], m[4]]);
-                                let timestamp = u64::from_be_bytes([0, 0, m[5], m[6], 
-                                                            m[7], m[8], m[9], m[10]]);
-                                let stock = String::from_utf8_lossy(&m[11..19]);
-                                let market_category: char = m[19].into();
-                                let financial_status_indicator: char= m[20].into();
-                                let round_lot_size = u32::from_be_bytes([m[21], m[22],
-                                                                       m[23], m[24]]);
-                                let round_lots_only: char= m[25].into();
-                                let issue_classification: char = m[26].into();
-                                let issue_sub_type = String::from_utf8_lossy(&m[27..29]);
-                                let authenticity: char = m[29].into();
-                                let short_sale_threshold_indicator: char = m[30].into();
-                                let ipo_flag: char = m[31].into();
-                                let luld_reference_price_tier: char = m[32].into();
-                                let etp_flag: char = m[33].into();
-                                let etp_leverage_factor = u32::from_be_bytes([m[34], 
-                                                            m[35], m[36], m[37]]);
-                                let inverse_indicator: char = m[38].into();
-                                csv_file_1.write_fmt(format_args!("{},{},{},{}.{:09},{},{},{},{},{},{},{},{},{},{},{},{},{},{}\n",
-                                    t, stock_locate, tracking_number,
-                                    timestamp/1000000000, timestamp%1000000000,
-                                    stock, market_category, financial_status_indicator,
-                                    round_lot_size, round_lots_only, issue_classification,
-                                    issue_sub_type, authenticity,
-                                    short_sale_threshold_indicator, ipo_flag,
-                                    luld_reference_price_tier, etp_flag, 
-                                    etp_leverage_factor, inverse_indicator)).expect("Can't write to csv file!");
-                                total_type[1] += 1;
-                                total += 1;
-                            },
-                            'H' => {
-                                let stock_locate = u16::from_be_bytes([m[1], m[2]]);
-                                let tracking_number = u16::from_be_bytes([m[3], m[4]]);
-                                let timestamp = u64::from_be_bytes([0, 0, m[5], m[6], 
-                                                            m[7], m[8], m[9], m[10]]);
-                                let stock = String::from_utf8_lossy(&m[11..19]);
-                                let trading_state: char = m[19].into();
-                                let reserved: char= m[20].into();
-                                let reason = String::from_utf8_lossy(&m[21..25]);
-                                csv_file_2.write_fmt(format_args!("{},{},{},{}.{:09},{},{},{},{}\n",
-                                    t, stock_locate, tracking_number,
-                                    timestamp/1000000000, timestamp%1000000000,
-                                    stock, trading_state, reserved, reason)).expect("Can't write to csv file!");
-                                total_type[2] += 1;
-                                total += 1;
-                            },
-                            'Y' => {
-                                let locate_code = u16::from_be_bytes([m[1], m[2]]);
-                                let tracking_number = u16::from_be_bytes([m[3], m[4]]);
-                                let timestamp = u64::from_be_bytes([0, 0, m[5], m[6], 
-                                                            m[7], m[8], m[9], m[10]]);
-                                let stock = String::from_utf8_lossy(&m[11..19]);
-                                let reg_sho_action: char = m[19].into();
-                                csv_file_3.write_fmt(format_args!("{},{},{},{}.{:09},{},{}\n",
-                                    t, locate_code, tracking_number,
-                                    timestamp/1000000000, timestamp%1000000000,
-                                    stock, reg_sho_action)).expect("Can't write to csv file!");
-                                total_type[3] += 1;
-                                total += 1;
-                            },
-                            'L' => {
-                                let stock_locate = u16::from_be_bytes([m[1], m[2]]);
-                                let tracking_number = u16::from_be_bytes([m[3], m[4]]);
-                                let timestamp = u64::from_be_bytes([0, 0, m[5], m[6], 
-                                                            m[7], m[8], m[9], m[10]]);
-                                let mpid = String::from_utf8_lossy(&m[11..15]);
-                                let stock = String::from_utf8_lossy(&m[15..23]);
-                                let primary_market_maker: char = m[23].into();
-                                let market_maker_mode: char = m[24].into();
-                                let market_participant_state: char= m[25].into();
-                                csv_file_4.write_fmt(format_args!("{},{},{},{}.{:09},{},{},{},{},{}\n",
-                                    t, stock_locate, tracking_number,
-                                    timestamp/1000000000, timestamp%1000000000,
-                                    mpid, stock, primary_market_maker, 
-                                    market_maker_mode, 
-                                    market_participant_state)).expect("Can't write to csv file!");
-                                total_type[4] += 1;
-                                total += 1;
-                            },
-                            'V' => {
-                                let stock_locate = u16::from_be_bytes([m[1], m[2]]);
-                                let tracking_number = u16::from_be_bytes([m[3], m[4]]);
-                                let timestamp = u64::from_be_bytes([0, 0, m[5], m[6], 
-                                                            m[7], m[8], m[9], m[10]]);
-                                let level1 = u64::from_be_bytes([m[11], m[12], m[13], 
-                                                    m[14], m[15], m[16], m[17], m[18]]);
-                                let level2 = u64::from_be_bytes([m[19], m[20], m[21], 
-                                                    m[22], m[23], m[24], m[25], m[26]]);
-                                let level3 = u64::from_be_bytes([m[27], m[28], m[29], 
-                                                    m[30], m[31], m[32], m[33], m[34]]);
-                                csv_file_5.write_fmt(format_args!("{},{},{},{}.{:09},{}.{:08},{}.{:08},{}.{:08}\n",
-                                    t, stock_locate, tracking_number,
-                                    timestamp/1000000000, timestamp%1000000000,
-                                    level1/100000000, level1%100000000,
-                                    level2/100000000, level2%100000000,
-                                    level3/100000000, level3%100000000)).expect("Can't write to csv file!");
-                                total_type[5] += 1;
-                                total += 1;
-                            },
-                            'W' => {
-                                let stock_locate = u16::from_be_bytes([m[1], m[2]]);
-                                let tracking_number = u16::from_be_bytes([m[3], m[4]]);
-                                let timestamp = u64::from_be_bytes([0, 0, m[5], m[6], 
-                                                            m[7], m[8], m[9], m[10]]);
-                                let breached_level : char = m[11].into();
-                                csv_file_6.write_fmt(format_args!("{},{},{},{}.{:09},{}\n",
-                                    t, stock_locate, tracking_number,
-                                    timestamp/1000000000, timestamp%1000000000,
-                                    breached_level)).expect("Can't write to csv file!");
-                                total_type[6] += 1;
-                                total += 1;
-                            },
-                            'K' => {
-                                let stock_locate = u16::from_be_bytes([m[1], m[2]]);
-                                let tracking_number = u16::from_be_bytes([m[3], m[4]]);
-                                let timestamp = u64::from_be_bytes([0, 0, m[5], m[6], 
-                                                            m[7], m[8], m[9], m[10]]);
-                                let stock = String::from_utf8_lossy(&m[11..19]);
-                                let ipo_quotation_release_time = u32::from_be_bytes([m[19], m[20],
-                                                                       m[21], m[22]]);
-                                let ipo_quotation_release_qualifier: char = m[23].into();
-                                let ipo_price = u32::from_be_bytes([m[24], m[25],
-                                                                    m[26], m[27]]);
-                                csv_file_7.write_fmt(format_args!("{},{},{},{}.{:09},{},{},{},{}.{:04}\n",
-                                    t, stock_locate, tracking_number,
-                                    timestamp/1000000000, timestamp%1000000000,
-                                    stock, ipo_quotation_release_time,
-                                    ipo_quotation_release_qualifier, 
-                                    ipo_price/10000, ipo_price%10000)).expect("Can't write to csv file!");
-                                total_type[7] += 1;
-                                total += 1;
-                            },
-                            'J' => {
-                                let stock_locate = u16::from_be_bytes([m[1], m[2]]);
-                                let tracking_number = u16::from_be_bytes([m[3], m[4]]);
-                                let timestamp = u64::from_be_bytes([0, 0, m[5], m[6], 
-                                                            m[7], m[8], m[9], m[10]]);
-                                let stock = String::from_utf8_lossy(&m[11..19]);
-                                let acrp = u32::from_be_bytes([m[19], m[20],
-                                                            m[21], m[22]]);
-                                let uacp = u32::from_be_bytes([m[23], m[24],
-                                                            m[25], m[26]]);
-                                let lacp = u32::from_be_bytes([m[27], m[28],
-                                                            m[29], m[30]]);
-                                let auction_collar_extension = u32::from_be_bytes([m[31], m[32],
-                                                            m[33], m[34]]);
-                                csv_file_8.write_fmt(format_args!("{},{},{},{}.{:09},{},{}.{:04},{}.{:04},{}.{:04},{}\n",
-                                    t, stock_locate, tracking_number,
-                                    timestamp/1000000000, timestamp%1000000000,
-                                    stock, acrp/10000, acrp%10000, uacp/10000, uacp%10000,
-                                    lacp/10000, lacp%10000, 
-                                    auction_collar_extension)).expect("Can't write to csv file!");
-                                total_type[8] += 1;
-                                total += 1;
-                            },
-                            'h' => {
-                                let stock_locate = u16::from_be_bytes([m[1], m[2]]);
-                                let tracking_number = u16::from_be_bytes([m[3], m[4]]);
-                                let timestamp = u64::from_be_bytes([0, 0, m[5], m[6], 
-                                                            m[7], m[8], m[9], m[10]]);
-                                let stock = String::from_utf8_lossy(&m[11..19]);
-                                let market_code: char = m[19].into();
-                                let operational_halt_action: char= m[20].into();
-                                csv_file_9.write_fmt(format_args!("{},{},{},{}.{:09},{},{},{}\n",
-                                    t, stock_locate, tracking_number,
-                                    timestamp/1000000000, timestamp%1000000000,
-                                    stock, market_code, 
-                                    operational_halt_action)).expect("Can't write to csv file!");
-                                total_type[9] += 1;
-                                total += 1;
-                            },
-                            'A' => {
-                                let stock_locate = u16::from_be_bytes([m[1], m[2]]);
-                                let tracking_number = u16::from_be_bytes([m[3], m[4]]);
-                                let timestamp = u64::from_be_bytes([0, 0, m[5], m[6], 
-                                                            m[7], m[8], m[9], m[10]]);
-                                let order_reference_number = 
-                                    u64::from_be_bytes([m[11], m[12], m[13], m[14], 
-                                                        m[15], m[16], m[17], m[18]]);
-                                let buy_sell_indicator: char = m[19].into();
-                                let shares = u32::from_be_bytes([m[20], m[21],
-                                                                 m[22], m[23]]);
-                                let stock = String::from_utf8_lossy(&m[24..31]);
-                                let price = u32::from_be_bytes([m[32], m[33],
-                                                                m[34], m[35]]);
-                                csv_file_10.write_fmt(format_args!("{},{},{},{}.{:09},{},{},{},{},{}.{:04}\n",
-                                    t, stock_locate, tracking_number,
-                                    timestamp/1000000000, timestamp%1000000000,
-                                    order_reference_number, buy_sell_indicator,
-                                    shares, stock,
-                                    price/10000, price%10000)).expect("Can't write to csv file!");
-                                total_type[10] += 1;
-                                total += 1;
-                            },
-                            'F' => {
-                                let stock_locate = u16::from_be_bytes([m[1], m[2]]);
-                                let tracking_number = u16::from_be_bytes([m[3], m[4]]);
-                                let timestamp = u64::from_be_bytes([0, 0, m[5], m[6], 
-                                                            m[7], m[8], m[9], m[10]]);
-                                let order_reference_number =
-                                    u64::from_be_bytes([m[11], m[12], m[13], m[14],
-                                                        m[15], m[16], m[17], m[18]]);
-                                let buy_sell_indicator: char = m[19].into();
-                                let shares = u32::from_be_bytes([m[20], m[21],
-                                                                 m[22], m[23]]);
-                                let stock = String::from_utf8_lossy(&m[24..31]);
-                                let price = u32::from_be_bytes([m[32], m[33],
-                                                                m[34], m[35]]);
-                                let attribution = String::from_utf8_lossy(&m[36..40]);
-                                csv_file_11.write_fmt(format_args!("{},{},{},{}.{:09},{},{},{},{},{}.{:04},{}\n",
-                                    t, stock_locate, tracking_number,
-                                    timestamp/1000000000, timestamp%1000000000,
-                                    order_reference_number, buy_sell_indicator,
-                                    shares, stock,
-                                    price/10000, price%10000, 
-                                    attribution)).expect("Can't write to csv file!");
-                                total_type[11] += 1;
-                                total += 1;
-                            },
-                            'E' => {
-                                let stock_locate = u16::from_be_bytes([m[1], m[2]]);
-                                let tracking_number = u16::from_be_bytes([m[3], m[4]]);
-                                let timestamp = u64::from_be_bytes([0, 0, m[5], m[6], 
-                                                            m[7], m[8], m[9], m[10]]);
-                                let order_reference_number =
-                                    u64::from_be_bytes([m[11], m[12], m[13], m[14],
-                                                        m[15], m[16], m[17], m[18]]);
-                                let executed_shares = 
-                                    u32::from_be_bytes([m[19], m[20], m[21], m[22]]);
-                                let match_number = 
-                                    u64::from_be_bytes([m[23], m[24], m[25], m[26], 
-                                                        m[27], m[28], m[29], m[30]]);
-                                csv_file_12.write_fmt(format_args!("{},{},{},{}.{:09},{},{},{}\n",
-                                    t, stock_locate, tracking_number,
-                                    timestamp/1000000000, timestamp%1000000000,
-                                    order_reference_number, executed_shares,
-                                    match_number)).expect("Can't write to csv file!");
-                                total_type[12] += 1;
-                                total += 1;
-                            },
-                            'C' => {
-                                let stock_locate = u16::from_be_bytes([m[1], m[2]]);
-                                let tracking_number = u16::from_be_bytes([m[3], m[4]]);
-                                let timestamp = u64::from_be_bytes([0, 0, m[5], m[6], 
-                                                            m[7], m[8], m[9], m[10]]);
-                                let order_reference_number =
-                                    u64::from_be_bytes([m[11], m[12], m[13], m[14],
-                                                        m[15], m[16], m[17], m[18]]);
-                                let executed_shares = 
-                                    u32::from_be_bytes([m[19], m[20], m[21], m[22]]);
-                                let match_number = 
-                                    u64::from_be_bytes([m[23], m[24], m[25], m[26], 
-                                                        m[27], m[28], m[29], m[30]]);
-                                let printable: char = m[31].into();
-                                let execution_price = u32::from_be_bytes([m[32], m[33],
-                                                                       m[34], m[35]]);
-                                csv_file_13.write_fmt(format_args!("{},{},{},{}.{:09},{},{},{},{},{}.{:04}\n",
-                                    t, stock_locate, tracking_number,
-                                    timestamp/1000000000, timestamp%1000000000,
-                                    order_reference_number, executed_shares,
-                                    match_number, printable,
-                                    execution_price/10000, 
-                                    execution_price%10000)).expect("Can't write to csv file!");
-                                total_type[13] += 1;
-                                total += 1;
-                            },
-                            'X' => {
-                                let stock_locate = u16::from_be_bytes([m[1], m[2]]);
-                                let tracking_number = u16::from_be_bytes([m[3], m[4]]);
-                                let timestamp = u64::from_be_bytes([0, 0, m[5], m[6], 
-                                                            m[7], m[8], m[9], m[10]]);
-                                let order_reference_number =
-                                    u64::from_be_bytes([m[11], m[12], m[13], m[14],
-                                                        m[15], m[16], m[17], m[18]]);
-                                let cancelled_shares = 
-                                    u32::from_be_bytes([m[19], m[20], m[21], m[22]]);
-                                csv_file_14.write_fmt(format_args!("{},{},{},{}.{:09},{},{}\n",
-                                    t, stock_locate, tracking_number,
-                                    timestamp/1000000000, timestamp%1000000000,
-                                    order_reference_number, 
-                                    cancelled_shares)).expect("Can't write to csv file!");
-                                total_type[14] += 1;
-                                total += 1;
-                            },
-                            'D' => {
-                                let stock_locate = u16::from_be_bytes([m[1], m[2]]);
-                                let tracking_number = u16::from_be_bytes([m[3], m[4]]);
-                                let timestamp = u64::from_be_bytes([0, 0, m[5], m[6], 
-                                                            m[7], m[8], m[9], m[10]]);
-                                let order_reference_number =
-                                    u64::from_be_bytes([m[11], m[12], m[13], m[14],
-                                                        m[15], m[16], m[17], m[18]]);
-                                csv_file_15.write_fmt(format_args!("{},{},{},{}.{:09},{}\n",
-                                    t, stock_locate, tracking_number,
-                                    timestamp/1000000000, timestamp%1000000000,
-                                    order_reference_number)).expect("Can't write to csv file!");
-                                total_type[15] += 1;
-                                total += 1;
-                            },
-                            'U' => {
-                                let stock_locate = u16::from_be_bytes([m[1], m[2]]);
-                                let tracking_number = u16::from_be_bytes([m[3], m[4]]);
-                                let timestamp = u64::from_be_bytes([0, 0, m[5], m[6], 
-                                                            m[7], m[8], m[9], m[10]]);
-                                let original_order_reference_number =
-                                    u64::from_be_bytes([m[11], m[12], m[13], m[14],
-                                                        m[15], m[16], m[17], m[18]]);
-                                let new_order_reference_number =
-                                    u64::from_be_bytes([m[19], m[20], m[21], m[22],
-                                                        m[23], m[24], m[25], m[26]]);
-                                let shares = 
-                                    u32::from_be_bytes([m[27], m[28], m[29], m[30]]);
-                                let price = u32::from_be_bytes([m[31], m[32],
-                                                                m[33], m[34]]);
-                                csv_file_16.write_fmt(format_args!("{},{},{},{}.{:09},{},{},{},{}.{:04}\n",
-                                    t, stock_locate, tracking_number,
-                                    timestamp/1000000000, timestamp%1000000000,
-                                    original_order_reference_number,
-                                    new_order_reference_number,
-                                    shares, price/10000, price%10000)).expect("Can't write to csv file!");
-                                total_type[16] += 1;
-                                total += 1;
-                            },
-                            'P' => {
-                                let stock_locate = u16::from_be_bytes([m[1], m[2]]);
-                                let tracking_number = u16::from_be_bytes([m[3], m[4]]);
-                                let timestamp = u64::from_be_bytes([0, 0, m[5], m[6], 
-                                                            m[7], m[8], m[9], m[10]]);
-                                let order_reference_number =
-                                    u64::from_be_bytes([m[11], m[12], m[13], m[14],
-                                                        m[15], m[16], m[17], m[18]]);
-                                let buy_sell_indicator: char = m[19].into();
-                                let shares = u32::from_be_bytes([m[20], m[21],
-                                                                 m[22], m[23]]);
-                                let stock = String::from_utf8_lossy(&m[24..31]);
-                                let price = u32::from_be_bytes([m[32], m[33],
-                                                                m[34], m[35]]);
-                                let match_number = 
-                                    u64::from_be_bytes([m[36], m[37], m[38], m[39],
-                                                        m[40], m[41], m[42], m[43]]);
-                                csv_file_17.write_fmt(format_args!("{},{},{},{}.{:09},{},{},{},{},{}.{:04},{}\n",
-                                    t, stock_locate, tracking_number,
-                                    timestamp/1000000000, timestamp%1000000000,
-                                    order_reference_number, buy_sell_indicator, 
-                                    shares, stock,
-                                    price/10000, price%10000, match_number)).expect("Can't write to csv file!");
-                                total_type[17] += 1;
-                                total += 1;
-                            },
-                            'Q' => {
-                                let stock_locate = u16::from_be_bytes([m[1], m[2]]);
-                                let tracking_number = u16::from_be_bytes([m[3], m[4]]);
-                                let timestamp = u64::from_be_bytes([0, 0, m[5], m[6], 
-                                                            m[7], m[8], m[9], m[10]]);
-                                let shares = 
-                                    u64::from_be_bytes([m[11], m[12], m[13], m[14],
-                                                        m[15], m[16], m[17], m[18]]);
-                                let stock = String::from_utf8_lossy(&m[19..27]);
-                                let cross_price = u32::from_be_bytes([m[27], m[28],
-                                                                m[29], m[30]]);
-                                let match_number = 
-                                    u64::from_be_bytes([m[31], m[32], m[33], m[34],
-                                                        m[35], m[36], m[37], m[38]]);
-                                let cross_type: char = m[39].into();
-                                csv_file_18.write_fmt(format_args!("{},{},{},{}.{:09},{},{},{}.{:04},{},{}\n",
-                                    t, stock_locate, tracking_number,
-                                    timestamp/1000000000, timestamp%1000000000,
-                                    shares, stock,
-                                    cross_price/10000, cross_price%10000,
-                                    match_number, cross_type)).expect("Can't write to csv file!");
-                                total_type[18] += 1;
-                                total += 1;
-                            },
-                            'B' => {
-                                let stock_locate = u16::from_be_bytes([m[1], m[2]]);
-                                let tracking_number = u16::from_be_bytes([m[3], m[4]]);
-                                let timestamp = u64::from_be_bytes([0, 0, m[5], m[6], 
-                                                            m[7], m[8], m[9], m[10]]);
-                                let match_number = 
-                                    u64::from_be_bytes([m[11], m[12], m[13], m[14],
-                                                        m[15], m[16], m[17], m[18]]);
-                                csv_file_19.write_fmt(format_args!("{},{},{},{}.{},{}\n",
-                                    t, stock_locate, tracking_number,
-                                    timestamp/1000000000, timestamp%1000000000,
-                                    match_number)).expect("Can't write to csv file!");
-                                total_type[19] += 1;
-                                total += 1;
-                            },
-                            'I' => {
-                                let stock_locate = u16::from_be_bytes([m[1], m[2]]);
-                                let tracking_number = u16::from_be_bytes([m[3], m[4]]);
-                                let timestamp = u64::from_be_bytes([0, 0, m[5], m[6], 
-                                                            m[7], m[8], m[9], m[10]]);
-                                let paired_shares = 
-                                    u64::from_be_bytes([m[11], m[12], m[13], m[14],
-                                                        m[15], m[16], m[17], m[18]]);
-                                let imbalance_shares = 
-                                    u64::from_be_bytes([m[19], m[20], m[21], m[22],
-                                                        m[23], m[24], m[25], m[26]]);
-                                let imbalance_direction: char = m[27].into();
-                                let stock = String::from_utf8_lossy(&m[28..36]);
-                                let far_price = u32::from_be_bytes([m[36], m[37],
-                                                                       m[38], m[39]]);
-                                let near_price = u32::from_be_bytes([m[40], m[41],
-                                                                       m[42], m[43]]);
-                                let current_ref_price = u32::from_be_bytes([m[44], m[45],
-                                                                       m[46], m[47]]);
-                                let cross_type: char= m[48].into();
-                                let price_variation_indicator: char = m[49].into();
-                                csv_file_20.write_fmt(format_args!("{},{},{},{}.{:09},{},{},{},{},{}.{:04},{}.{:04},{}.{:04},{},{}\n",
-                                    t, stock_locate, tracking_number,
-                                    timestamp/1000000000, timestamp%1000000000,
-                                    paired_shares, imbalance_shares, 
-                                    imbalance_direction, stock,
-                                    far_price/10000, far_price%10000,
-                                    near_price/10000, near_price%10000,
-                                    current_ref_price/10000, current_ref_price%10000,
-                                    cross_type, price_variation_indicator)).expect("Can't write to csv file!");
-                                total_type[20] += 1;
-                                total += 1;
-                            },
-                            'N' => {
-                                let stock_locate = u16::from_be_bytes([m[1], m[2]]);
-                                let tracking_number = u16::from_be_bytes([m[3], m[4]]);
-                                let timestamp = u64::from_be_bytes([0, 0, m[5], m[6], 
-                                                            m[7], m[8], m[9], m[10]]);
-                                let stock = String::from_utf8_lossy(&m[11..19]);
-                                let interest_flag: char = m[19].into();
-                                csv_file_21.write_fmt(format_args!("{},{},{},{}.{:09},{},{}\n",
-                                    t, stock_locate, tracking_number,
-                                    timestamp/1000000000, timestamp%1000000000,
-                                    stock, interest_flag)).expect("Can't write to csv file!");
-                                total_type[21] += 1;
-                                total += 1;
-                            },
-                            _ => { 
-                                eprintln!("How could it be? An unrecognized type: {}! I am freaking out...", t);
-                                std::process::exit(1);
-                            }
-                        }
-                    },
-                    Err(_e) => {
-                        eprintln!("Something wrong!");
-                        std::process::exit(1);
-                    },
-                }
-            },
-            Err(_e) => {
-                println!("EOF");
-                // clean up
-                break;
-            },
+    let stats = match result {
+        Ok(stats) => stats,
+        Err(e) => {
+            eprintln!("Something wrong! {}", e);
+            std::process::exit(1);
         }
-    }
+    };
+    println!("=========== Parsing ITCH v5.0 ends   ===========");
     
-    println!("Total number of all messages parsed: {}", total);
-    for i in 0..22 {
-        println!("Total number of {} messages parsed: {}", msg_type[i], total_type[i]);
+    println!("Total number of all messages parsed: {}", stats.total);
+    for (t, n) in MESSAGE_TYPES.iter().zip(stats.by_type) {
+        println!("Total number of {} messages parsed: {}", *t as char, n);
     }
 
     match start.elapsed() {
@@ -828,3 +534,51 @@ fn main() {
        }
    }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: u64 = 1_000_000_000;
+
+    #[test]
+    fn times() {
+        assert_eq!(parse_time("00:00"), Some(0));
+        assert_eq!(parse_time("09:30"), Some((9 * 3600 + 30 * 60) * SECOND));
+        assert_eq!(parse_time("09:30:01"), Some((9 * 3600 + 30 * 60 + 1) * SECOND));
+        assert_eq!(parse_time("09:30:00.5"), Some((9 * 3600 + 30 * 60) * SECOND + SECOND / 2));
+        assert_eq!(parse_time("09:30:00.000000001"), Some((9 * 3600 + 30 * 60) * SECOND + 1));
+        assert_eq!(parse_time("24:00:00"), Some(24 * 3600 * SECOND));
+        for bad in [
+            "9:30",
+            "09:60",
+            "09:30:60",
+            "09:30.5",
+            "09:30:00.",
+            "09:30:00.0000000001",
+            "09:30:00.5x",
+            "09",
+            "09:30:00:00",
+            "24:00:00.000000001",
+            "24:00:01",
+            "24:59",
+            "25:00",
+            "",
+        ] {
+            assert_eq!(parse_time(bad), None, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("500ns"), Some(500));
+        assert_eq!(parse_duration("250us"), Some(250_000));
+        assert_eq!(parse_duration("100ms"), Some(SECOND / 10));
+        assert_eq!(parse_duration("1s"), Some(SECOND));
+        assert_eq!(parse_duration("5m"), Some(300 * SECOND));
+        assert_eq!(parse_duration("1h"), Some(3600 * SECOND));
+        for bad in ["0s", "100", "ms", "1.5s", "-1s", "1 s", "1d", "99999999999999h", ""] {
+            assert_eq!(parse_duration(bad), None, "{}", bad);
+        }
+    }
+}
@@ -0,0 +1,491 @@
+// (C) Copyright 2020 Shawfeng Dong. All rights reserved.
+// Use of this source code is governed by an MIT-style
+// license that can be found in the LICENSE file.
+
+//! Limit order book reconstruction from the order messages.
+//!
+//! [`OrderBook`] keeps every resting order by its order reference number,
+//! and one [`Book`] of price levels per stock locate:
+//!
+//! - `A` and `F` add an order,
+//! - `E`, `C` and `X` take shares off an order, removing it once none are
+//!   left,
+//! - `D` removes an order,
+//! - `U` removes the original order and adds the new reference number on
+//!   the same side, with the new price and size.
+//!
+//! Other messages leave the book alone. Orders added before the start of
+//! the input are unknown to the book, so messages that refer to them are
+//! ignored.
+
+use std::collections::btree_map::{self, BTreeMap};
+use std::collections::HashMap;
+use std::hash::{BuildHasherDefault, Hasher};
+use std::io;
+use std::iter::Rev;
+
+use crate::message::Message;
+use crate::sink::{Context, OutputSink};
+
+/// Side of an order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+impl Side {
+    /// Side from a buy/sell indicator, `B` or `S`.
+    pub fn from_indicator(c: u8) -> Option<Side> {
+        match c {
+            b'B' => Some(Side::Buy),
+            b'S' => Some(Side::Sell),
+            _ => None,
+        }
+    }
+
+    /// The buy/sell indicator, `B` or `S`.
+    pub fn indicator(self) -> u8 {
+        match self {
+            Side::Buy => b'B',
+            Side::Sell => b'S',
+        }
+    }
+
+    pub fn opposite(self) -> Side {
+        match self {
+            Side::Buy => Side::Sell,
+            Side::Sell => Side::Buy,
+        }
+    }
+}
+
+/// A resting order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Order {
+    pub stock_locate: u16,
+    pub side: Side,
+    /// `Price(4)`.
+    pub price: u32,
+    /// Shares still displayed.
+    pub shares: u32,
+}
+
+/// All resting orders at one price on one side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Level {
+    /// `Price(4)`.
+    pub price: u32,
+    pub shares: u64,
+    pub orders: u32,
+}
+
+// Shares and order count at one price.
+#[derive(Debug, Clone, Copy, Default)]
+struct Depth {
+    shares: u64,
+    orders: u32,
+}
+
+/// The price levels of one stock.
+#[derive(Debug, Clone, Default)]
+pub struct Book {
+    bids: BTreeMap<u32, Depth>,
+    asks: BTreeMap<u32, Depth>,
+}
+
+impl Book {
+    /// Price levels of `side`, best first.
+    pub fn levels(&self, side: Side) -> Levels<'_> {
+        match side {
+            Side::Buy => Levels(LevelIter::Bids(self.bids.iter().rev())),
+            Side::Sell => Levels(LevelIter::Asks(self.asks.iter())),
+        }
+    }
+
+    /// Bid levels, highest price first.
+    pub fn bids(&self) -> Levels<'_> {
+        self.levels(Side::Buy)
+    }
+
+    /// Ask levels, lowest price first.
+    pub fn asks(&self) -> Levels<'_> {
+        self.levels(Side::Sell)
+    }
+
+    pub fn best_bid(&self) -> Option<Level> {
+        self.bids().next()
+    }
+
+    pub fn best_ask(&self) -> Option<Level> {
+        self.asks().next()
+    }
+
+    /// Whether both sides are empty.
+    pub fn is_empty(&self) -> bool {
+        self.bids.is_empty() && self.asks.is_empty()
+    }
+
+    fn side_mut(&mut self, side: Side) -> &mut BTreeMap<u32, Depth> {
+        match side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        }
+    }
+
+    fn add(&mut self, side: Side, price: u32, shares: u32) {
+        let depth = self.side_mut(side).entry(price).or_default();
+        depth.shares += u64::from(shares);
+        depth.orders += 1;
+    }
+
+    // Takes `shares` off the level, and the order too if `gone`.
+    fn reduce(&mut self, side: Side, price: u32, shares: u32, gone: bool) {
+        let levels = self.side_mut(side);
+        if let btree_map::Entry::Occupied(mut e) = levels.entry(price) {
+            let depth = e.get_mut();
+            depth.shares = depth.shares.saturating_sub(u64::from(shares));
+            if gone {
+                depth.orders -= 1;
+            }
+            if depth.orders == 0 {
+                e.remove();
+            }
+        }
+    }
+}
+
+/// Iterator over the price levels of one side of a [`Book`], best first.
+#[derive(Debug, Clone)]
+pub struct Levels<'a>(LevelIter<'a>);
+
+#[derive(Debug, Clone)]
+enum LevelIter<'a> {
+    Bids(Rev<btree_map::Iter<'a, u32, Depth>>),
+    Asks(btree_map::Iter<'a, u32, Depth>),
+}
+
+impl Iterator for Levels<'_> {
+    type Item = Level;
+
+    fn next(&mut self) -> Option<Level> {
+        let (&price, depth) = match &mut self.0 {
+            LevelIter::Bids(it) => it.next()?,
+            LevelIter::Asks(it) => it.next()?,
+        };
+        Some(Level { price, shares: depth.shares, orders: depth.orders })
+    }
+}
+
+// Order reference numbers are assigned sequentially, so a multiplicative
+// hash spreads them well enough and is much cheaper than SipHash.
+#[derive(Default)]
+struct RefHasher(u64);
+
+impl Hasher for RefHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = (self.0.rotate_left(8) ^ u64::from(b)).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        }
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        self.0 = n.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    }
+}
+
+/// Order book of every stock in the feed, built by applying the order
+/// messages in feed order. Also an [`OutputSink`], so it can be driven by a
+/// [`Parser`](crate::Parser) directly.
+#[derive(Default)]
+pub struct OrderBook {
+    orders: HashMap<u64, Order, BuildHasherDefault<RefHasher>>,
+    // indexed by stock locate
+    books: Vec<Book>,
+}
+
+impl OrderBook {
+    pub fn new() -> Self {
+        OrderBook::default()
+    }
+
+    /// Applies one message. Returns whether the book changed, which is only
+    /// the case for order messages about known orders.
+    pub fn apply(&mut self, msg: &Message) -> bool {
+        match msg {
+            Message::AddOrder(m) => self.add(
+                m.order_reference_number, m.stock_locate, m.buy_sell_indicator, m.price, m.shares,
+            ),
+            Message::AddOrderMpid(m) => self.add(
+                m.order_reference_number, m.stock_locate, m.buy_sell_indicator, m.price, m.shares,
+            ),
+            Message::OrderExecuted(m) => self.reduce(m.order_reference_number, m.executed_shares),
+            Message::OrderExecutedWithPrice(m) => {
+                self.reduce(m.order_reference_number, m.executed_shares)
+            }
+            Message::OrderCancel(m) => self.reduce(m.order_reference_number, m.cancelled_shares),
+            Message::OrderDelete(m) => self.remove(m.order_reference_number).is_some(),
+            Message::OrderReplace(m) => match self.remove(m.original_order_reference_number) {
+                Some(order) => self.add(
+                    m.new_order_reference_number, order.stock_locate, order.side.indicator(),
+                    m.price, m.shares,
+                ),
+                None => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// The resting order with reference number `reference`.
+    pub fn order(&self, reference: u64) -> Option<&Order> {
+        self.orders.get(&reference)
+    }
+
+    /// Number of resting orders across all stocks.
+    pub fn order_count(&self) -> usize {
+        self.orders.len()
+    }
+
+    /// The price levels of the stock with locate code `stock_locate`. `None`
+    /// only if no stock with this or a higher locate code has had an order;
+    /// a stock that never had one may still get an empty book.
+    pub fn book(&self, stock_locate: u16) -> Option<&Book> {
+        self.books.get(usize::from(stock_locate))
+    }
+
+    /// Stock locates and their books, skipping empty books.
+    pub fn books(&self) -> impl Iterator<Item = (u16, &Book)> {
+        self.books
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| !b.is_empty())
+            .map(|(i, b)| (i as u16, b))
+    }
+
+    fn book_mut(&mut self, stock_locate: u16) -> &mut Book {
+        let i = usize::from(stock_locate);
+        if i >= self.books.len() {
+            self.books.resize_with(i + 1, Book::default);
+        }
+        &mut self.books[i]
+    }
+
+    fn add(
+        &mut self,
+        reference: u64,
+        stock_locate: u16,
+        indicator: u8,
+        price: u32,
+        shares: u32,
+    ) -> bool {
+        let side = match Side::from_indicator(indicator) {
+            Some(side) => side,
+            None => return false,
+        };
+        // a reused reference number replaces the old order
+        self.remove(reference);
+        self.orders.insert(reference, Order { stock_locate, side, price, shares });
+        self.book_mut(stock_locate).add(side, price, shares);
+        true
+    }
+
+    fn reduce(&mut self, reference: u64, shares: u32) -> bool {
+        let order = match self.orders.get_mut(&reference) {
+            Some(order) => order,
+            None => return false,
+        };
+        let shares = shares.min(order.shares);
+        order.shares -= shares;
+        let order = *order;
+        let gone = order.shares == 0;
+        if gone {
+            self.orders.remove(&reference);
+        }
+        self.book_mut(order.stock_locate).reduce(order.side, order.price, shares, gone);
+        true
+    }
+
+    fn remove(&mut self, reference: u64) -> Option<Order> {
+        let order = self.orders.remove(&reference)?;
+        self.book_mut(order.stock_locate).reduce(order.side, order.price, order.shares, true);
+        Some(order)
+    }
+}
+
+impl OutputSink for OrderBook {
+    fn on_message(&mut self, _ctx: &Context, msg: &Message) -> io::Result<()> {
+        self.apply(msg);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::*;
+
+    const LOCATE: u16 = 7;
+
+    fn add(reference: u64, indicator: u8, shares: u32, price: u32) -> Message {
+        Message::AddOrder(AddOrder {
+            stock_locate: LOCATE,
+            tracking_number: 0,
+            timestamp: 0,
+            order_reference_number: reference,
+            buy_sell_indicator: indicator,
+            shares,
+            stock: *b"AAPL    ",
+            price,
+        })
+    }
+
+    fn execute(reference: u64, shares: u32) -> Message {
+        Message::OrderExecuted(OrderExecuted {
+            stock_locate: LOCATE,
+            tracking_number: 0,
+            timestamp: 0,
+            order_reference_number: reference,
+            executed_shares: shares,
+            match_number: 1,
+        })
+    }
+
+    fn cancel(reference: u64, shares: u32) -> Message {
+        Message::OrderCancel(OrderCancel {
+            stock_locate: LOCATE,
+            tracking_number: 0,
+            timestamp: 0,
+            order_reference_number: reference,
+            cancelled_shares: shares,
+        })
+    }
+
+    fn delete(reference: u64) -> Message {
+        Message::OrderDelete(OrderDelete {
+            stock_locate: LOCATE,
+            tracking_number: 0,
+            timestamp: 0,
+            order_reference_number: reference,
+        })
+    }
+
+    fn replace(original: u64, new: u64, shares: u32, price: u32) -> Message {
+        Message::OrderReplace(OrderReplace {
+            stock_locate: LOCATE,
+            tracking_number: 0,
+            timestamp: 0,
+            original_order_reference_number: original,
+            new_order_reference_number: new,
+            shares,
+            price,
+        })
+    }
+
+    fn level(price: u32, shares: u64, orders: u32) -> Level {
+        Level { price, shares, orders }
+    }
+
+    fn levels(book: &OrderBook, side: Side) -> Vec<Level> {
+        book.book(LOCATE).map_or_else(Vec::new, |b| b.levels(side).collect())
+    }
+
+    #[test]
+    fn add_orders() {
+        let mut book = OrderBook::new();
+        assert!(book.apply(&add(1, b'B', 100, 1_000_000)));
+        assert!(book.apply(&add(2, b'B', 50, 1_000_000)));
+        assert!(book.apply(&add(3, b'B', 10, 1_010_000)));
+        assert!(book.apply(&add(4, b'S', 30, 1_020_000)));
+        assert_eq!(
+            levels(&book, Side::Buy),
+            [level(1_010_000, 10, 1), level(1_000_000, 150, 2)]
+        );
+        assert_eq!(levels(&book, Side::Sell), [level(1_020_000, 30, 1)]);
+        assert_eq!(book.order_count(), 4);
+        let order = Order { stock_locate: LOCATE, side: Side::Buy, price: 1_000_000, shares: 50 };
+        assert_eq!(book.order(2), Some(&order));
+    }
+
+    #[test]
+    fn partial_and_full_execution() {
+        let mut book = OrderBook::new();
+        book.apply(&add(1, b'S', 100, 1_000_000));
+        book.apply(&add(2, b'S', 40, 1_000_000));
+        assert!(book.apply(&execute(1, 30)));
+        assert_eq!(book.order(1).map(|o| o.shares), Some(70));
+        assert_eq!(levels(&book, Side::Sell), [level(1_000_000, 110, 2)]);
+        assert!(book.apply(&execute(1, 70)));
+        assert_eq!(book.order(1), None);
+        assert_eq!(levels(&book, Side::Sell), [level(1_000_000, 40, 1)]);
+        assert!(book.apply(&execute(2, 40)));
+        assert!(book.book(LOCATE).unwrap().is_empty());
+        assert_eq!(book.order_count(), 0);
+    }
+
+    #[test]
+    fn cancel_and_delete() {
+        let mut book = OrderBook::new();
+        book.apply(&add(1, b'B', 100, 1_000_000));
+        book.apply(&add(2, b'B', 20, 990_000));
+        assert!(book.apply(&cancel(1, 25)));
+        assert_eq!(
+            levels(&book, Side::Buy),
+            [level(1_000_000, 75, 1), level(990_000, 20, 1)]
+        );
+        assert!(book.apply(&delete(1)));
+        assert_eq!(levels(&book, Side::Buy), [level(990_000, 20, 1)]);
+        // unknown orders leave the book alone
+        assert!(!book.apply(&delete(1)));
+        assert!(!book.apply(&cancel(9, 5)));
+        assert_eq!(book.order_count(), 1);
+    }
+
+    #[test]
+    fn replace_keeps_the_side() {
+        let mut book = OrderBook::new();
+        book.apply(&add(1, b'S', 100, 1_000_000));
+        assert!(book.apply(&replace(1, 2, 60, 1_010_000)));
+        assert_eq!(book.order(1), None);
+        let order = Order { stock_locate: LOCATE, side: Side::Sell, price: 1_010_000, shares: 60 };
+        assert_eq!(book.order(2), Some(&order));
+        assert_eq!(levels(&book, Side::Sell), [level(1_010_000, 60, 1)]);
+        assert!(levels(&book, Side::Buy).is_empty());
+        assert!(!book.apply(&replace(1, 3, 10, 1_000_000)));
+    }
+
+    #[test]
+    fn reused_reference_number() {
+        let mut book = OrderBook::new();
+        book.apply(&add(1, b'B', 100, 1_000_000));
+        assert!(book.apply(&add(1, b'S', 10, 1_020_000)));
+        assert_eq!(book.order_count(), 1);
+        assert!(levels(&book, Side::Buy).is_empty());
+        assert_eq!(levels(&book, Side::Sell), [level(1_020_000, 10, 1)]);
+    }
+
+    #[test]
+    fn reduce_beyond_remaining_shares() {
+        let mut book = OrderBook::new();
+        book.apply(&add(1, b'B', 100, 1_000_000));
+        book.apply(&add(2, b'B', 50, 1_000_000));
+        assert!(book.apply(&cancel(1, 500)));
+        assert_eq!(book.order(1), None);
+        assert_eq!(levels(&book, Side::Buy), [level(1_000_000, 50, 1)]);
+        assert!(book.apply(&execute(2, 80)));
+        assert!(book.book(LOCATE).unwrap().is_empty());
+    }
+
+    #[test]
+    fn books_below_the_highest_locate() {
+        let mut book = OrderBook::new();
+        assert!(book.book(LOCATE).is_none());
+        book.apply(&add(1, b'B', 100, 1_000_000));
+        assert!(book.book(LOCATE - 1).is_some_and(Book::is_empty));
+        assert!(book.book(LOCATE + 1).is_none());
+        assert_eq!(book.books().map(|(locate, _)| locate).collect::<Vec<_>>(), [LOCATE]);
+    }
+}
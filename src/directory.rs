@@ -0,0 +1,74 @@
+// (C) Copyright 2020 Shawfeng Dong. All rights reserved.
+// Use of this source code is governed by an MIT-style
+// license that can be found in the LICENSE file.
+
+//! Stock locate codes and the symbols they stand for.
+
+use std::io;
+
+use crate::message::Message;
+use crate::sink::{trim_alpha, Context, OutputSink};
+
+/// Maps stock locate codes to symbols, learned from the Stock Directory
+/// (`R`) messages as they stream past. Nasdaq sends the whole directory at
+/// the start of the day, before any other message about a stock.
+#[derive(Debug, Clone, Default)]
+pub struct Directory {
+    // indexed by stock locate
+    symbols: Vec<Option<String>>,
+}
+
+impl Directory {
+    pub fn new() -> Self {
+        Directory::default()
+    }
+
+    /// Learns the symbol of a Stock Directory message. Returns the locate
+    /// code for `R` messages and `None` for anything else.
+    pub fn apply(&mut self, msg: &Message) -> Option<u16> {
+        let m = match msg {
+            Message::StockDirectory(m) => m,
+            _ => return None,
+        };
+        let symbol = String::from_utf8_lossy(trim_alpha(&m.stock)).into_owned();
+        let i = usize::from(m.stock_locate);
+        if i >= self.symbols.len() {
+            self.symbols.resize(i + 1, None);
+        }
+        self.symbols[i] = Some(symbol);
+        Some(m.stock_locate)
+    }
+
+    /// Symbol of `stock_locate`, without padding, e.g. `AAPL`.
+    pub fn symbol(&self, stock_locate: u16) -> Option<&str> {
+        self.symbols.get(usize::from(stock_locate))?.as_deref()
+    }
+
+    /// Locate code of `symbol`.
+    pub fn locate(&self, symbol: &str) -> Option<u16> {
+        self.symbols.iter().position(|s| s.as_deref() == Some(symbol)).map(|i| i as u16)
+    }
+
+    /// Locate codes and symbols, in locate order.
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &str)> {
+        self.symbols
+            .iter()
+            .enumerate()
+            .filter_map(|(i, s)| Some((i as u16, s.as_deref()?)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.iter().flatten().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl OutputSink for Directory {
+    fn on_message(&mut self, _ctx: &Context, msg: &Message) -> io::Result<()> {
+        self.apply(msg);
+        Ok(())
+    }
+}
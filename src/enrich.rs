@@ -0,0 +1,118 @@
+// (C) Copyright 2020 Shawfeng Dong. All rights reserved.
+// Use of this source code is governed by an MIT-style
+// license that can be found in the LICENSE file.
+
+//! Enrichment of the messages that only refer to a stock or an order.
+//!
+//! `E`, `C`, `X`, `D` and `U` carry an order reference number but neither
+//! the stock nor the side of the order, and `B` only a stock locate.
+//! [`Enricher`] keeps the resting orders and the stock directory, so these
+//! can be joined as the messages stream past rather than after the fact.
+
+use crate::book::{OrderBook, Side};
+use crate::directory::Directory;
+use crate::message::Message;
+
+/// What [`Enricher::apply`] knows about a message beyond its own fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Enrichment<'a> {
+    /// Symbol of the stock locate, e.g. `AAPL`.
+    pub stock: Option<&'a str>,
+    /// Side of the order referred to; for `U`, of the original order.
+    pub side: Option<Side>,
+    /// `Price(4)` of the order referred to, before the message.
+    pub price: Option<u32>,
+}
+
+/// Names of the columns that enrichment adds to message type `t`, in
+/// order. Each is only filled in where known, e.g. `side` and `price` stay
+/// empty for orders added before the start of the input.
+///
+/// - `E`, `X` and `D`: `stock`, `side` and `price`,
+/// - `C` and `U`: `stock` and `side`, as they carry their own price,
+/// - `B`: `stock`.
+///
+/// Other types either have a `stock` field of their own or are
+/// market-wide, and get no columns.
+pub fn enriched_fields(t: u8) -> &'static [&'static str] {
+    match t {
+        b'E' | b'X' | b'D' => &["stock", "side", "price"],
+        b'C' | b'U' => &["stock", "side"],
+        b'B' => &["stock"],
+        _ => &[],
+    }
+}
+
+/// Keeps an order map and a stock locate to symbol map, and looks up the
+/// stock, side and price of each message in them. It needs to see the
+/// Stock Directory (`R`) and all order messages (`A`, `F`, `E`, `C`, `X`,
+/// `D` and `U`).
+#[derive(Default)]
+pub struct Enricher {
+    book: OrderBook,
+    directory: Directory,
+}
+
+impl Enricher {
+    pub fn new() -> Self {
+        Enricher::default()
+    }
+
+    /// Looks up `msg`, then applies it to the order and symbol maps.
+    pub fn apply(&mut self, msg: &Message) -> Enrichment<'_> {
+        let reference = match msg {
+            Message::OrderExecuted(m) => Some(m.order_reference_number),
+            Message::OrderExecutedWithPrice(m) => Some(m.order_reference_number),
+            Message::OrderCancel(m) => Some(m.order_reference_number),
+            Message::OrderDelete(m) => Some(m.order_reference_number),
+            Message::OrderReplace(m) => Some(m.original_order_reference_number),
+            _ => None,
+        };
+        let order = reference.and_then(|r| self.book.order(r)).copied();
+        self.book.apply(msg);
+        self.directory.apply(msg);
+        Enrichment {
+            stock: self.directory.symbol(msg.stock_locate()),
+            side: order.map(|o| o.side),
+            price: order.map(|o| o.price),
+        }
+    }
+
+    pub fn directory(&self) -> &Directory {
+        &self.directory
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+
+    #[test]
+    fn lookups() {
+        let mut enricher = Enricher::new();
+        enricher.apply(&directory(1, 7, "AAPL"));
+        let apply = |enricher: &mut Enricher, msg| {
+            let Enrichment { stock, side, price } = enricher.apply(&msg);
+            (stock.map(String::from), side, price)
+        };
+        let aapl = Some("AAPL".to_string());
+        let found = apply(&mut enricher, add(2, 7, 1, b'B', 100, 1_000_000));
+        assert_eq!(found, (aapl.clone(), None, None));
+        let found = apply(&mut enricher, execute(3, 7, 1, 10, 11));
+        assert_eq!(found, (aapl.clone(), Some(Side::Buy), Some(1_000_000)));
+        let found = apply(&mut enricher, execute_at(4, 7, 1, 10, 12, b'Y', 1_000_100));
+        assert_eq!(found, (aapl.clone(), Some(Side::Buy), Some(1_000_000)));
+        let found = apply(&mut enricher, cancel(5, 7, 1, 10));
+        assert_eq!(found, (aapl.clone(), Some(Side::Buy), Some(1_000_000)));
+        // the side and price of the original order, before the message
+        let found = apply(&mut enricher, replace(6, 7, 1, 2, 50, 1_010_000));
+        assert_eq!(found, (aapl.clone(), Some(Side::Buy), Some(1_000_000)));
+        let found = apply(&mut enricher, delete(7, 7, 2));
+        assert_eq!(found, (aapl.clone(), Some(Side::Buy), Some(1_010_000)));
+        // gone now, or never added
+        assert_eq!(apply(&mut enricher, delete(8, 7, 2)), (aapl.clone(), None, None));
+        assert_eq!(apply(&mut enricher, broken(9, 7, 11)), (aapl, None, None));
+        assert_eq!(apply(&mut enricher, broken(9, 8, 12)), (None, None, None));
+    }
+}
@@ -0,0 +1,100 @@
+// (C) Copyright 2020 Shawfeng Dong. All rights reserved.
+// Use of this source code is governed by an MIT-style
+// license that can be found in the LICENSE file.
+
+//! Opening ITCH input files, compressed or not.
+
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{self, Cursor, ErrorKind, Read};
+use std::path::Path;
+
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+
+/// Compression formats recognised on input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+
+impl Compression {
+    /// Recognises the format from the first bytes of a stream. A raw ITCH
+    /// feed starts with a length prefix of a few bytes, which none of the
+    /// magic numbers collide with.
+    pub fn from_magic(head: &[u8]) -> Compression {
+        if head.starts_with(&[0x1f, 0x8b]) {
+            Compression::Gzip
+        } else if head.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Compression::Zstd
+        } else if head.starts_with(b"BZh") {
+            Compression::Bzip2
+        } else {
+            Compression::None
+        }
+    }
+
+    /// Recognises the format from a file extension such as `gz` or `zst`.
+    pub fn from_extension(ext: &OsStr) -> Compression {
+        match ext.to_str() {
+            Some("gz") | Some("gzip") => Compression::Gzip,
+            Some("zst") | Some("zstd") => Compression::Zstd,
+            Some("bz2") | Some("bzip2") => Compression::Bzip2,
+            _ => Compression::None,
+        }
+    }
+}
+
+/// Opens `path` for reading, decompressing it on the fly when it is gzip,
+/// zstd or bzip2. The format is taken from the magic bytes, or failing that
+/// from the extension.
+pub fn open_input<P: AsRef<Path>>(path: P) -> io::Result<Box<dyn Read>> {
+    let path = path.as_ref();
+    let by_extension = path.extension().map_or(Compression::None, Compression::from_extension);
+    decompress(File::open(path)?, by_extension)
+}
+
+/// Wraps `reader` in a decoder for its compression format. `fallback` is
+/// used when the magic bytes don't identify one, e.g. for a format guessed
+/// from a file name.
+pub fn decompress<R: Read + 'static>(mut reader: R, fallback: Compression) -> io::Result<Box<dyn Read>> {
+    // peek at the magic bytes, then put them back in front of the stream
+    let mut head = [0u8; 4];
+    let mut n = 0;
+    while n < head.len() {
+        match reader.read(&mut head[n..]) {
+            Ok(0) => break,
+            Ok(k) => n += k,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    let compression = match Compression::from_magic(&head[..n]) {
+        Compression::None => fallback,
+        found => found,
+    };
+    let stream = Cursor::new(head).take(n as u64).chain(reader);
+    Ok(match compression {
+        Compression::None => Box::new(stream),
+        Compression::Gzip => Box::new(MultiGzDecoder::new(stream)),
+        Compression::Zstd => Box::new(zstd::stream::read::Decoder::new(stream)?),
+        Compression::Bzip2 => Box::new(MultiBzDecoder::new(stream)),
+    })
+}
+
+/// Base name for output files derived from an input path: the file stem,
+/// after dropping a compression suffix. Both `S051018-v50.txt` and
+/// `S051018-v50.txt.gz` give `S051018-v50`.
+pub fn output_stem(path: &Path) -> String {
+    let mut stem = path.file_stem().unwrap_or_default();
+    let compressed = path
+        .extension()
+        .is_some_and(|ext| Compression::from_extension(ext) != Compression::None);
+    if compressed {
+        stem = Path::new(stem).file_stem().unwrap_or(stem);
+    }
+    stem.to_string_lossy().into_owned()
+}
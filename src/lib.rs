@@ -0,0 +1,55 @@
+// (C) Copyright 2020 Shawfeng Dong. All rights reserved.
+// Use of this source code is governed by an MIT-style
+// license that can be found in the LICENSE file.
+
+//! NASDAQ TotalView-ITCH 5.0 decoder.
+//!
+//! Reference: Nasdaq TotalView-ITCH 5.0 Specification
+//!
+//! Every ITCH message on the wire is preceded by a 2-byte big-endian length.
+//! [`decode`] takes the message bytes that follow the length prefix and
+//! returns a typed [`Message`]. [`ItchFrameReader`] splits any byte stream
+//! into those frames, and [`MappedFile`] does the same over a memory map.
+//! [`open_input`] opens gzip, zstd and bzip2 files transparently.
+//!
+//! [`Parser`] ties it together: it reads frames from a [`FrameSource`],
+//! decodes them and hands the messages to an [`OutputSink`] such as
+//! [`CsvSink`], [`ParquetSink`], [`JsonlSink`] or [`SqliteSink`].
+//! [`BatchBuilder`] and [`BatchSink`] turn messages into Arrow record
+//! batches in memory, and [`OrderBook`] rebuilds the limit order book of
+//! every stock from the order messages. [`SnapshotSink`] writes the top
+//! levels of those books to CSV or Parquet, on an interval or on every
+//! change, and [`BboSink`] every change of the best bid or offer.
+//! [`TradeTape`] turns executions and crosses into prints, priced through
+//! the book, and [`TradesSink`] writes them out; [`BarsSink`] aggregates
+//! them into OHLCV bars. [`Enricher`] adds the stock, side and price of the
+//! order to the messages that only refer to it, and [`PartitionedSink`]
+//! splits the output into a folder per symbol or bucket of stocks.
+
+mod book;
+mod directory;
+mod enrich;
+mod input;
+mod message;
+mod mmap;
+mod parser;
+mod reader;
+mod tape;
+pub mod sink;
+#[cfg(test)]
+mod testing;
+
+pub use book::*;
+pub use directory::*;
+pub use enrich::*;
+pub use input::*;
+pub use message::*;
+pub use mmap::*;
+pub use parser::*;
+pub use reader::*;
+pub use tape::*;
+pub use sink::{
+    arrow_schema, ArrowSink, BarsSink, BatchBuilder, BatchSink, BboSink, Context, CsvSink,
+    InterleavedCsvSink, JsonlSink, OutputSink, ParquetSink, PartitionedSink, Partitioning,
+    SnapshotSink, SqliteSink, TableFormat, TradesSink, Trigger,
+};
@@ -0,0 +1,795 @@
+// (C) Copyright 2020 Shawfeng Dong. All rights reserved.
+// Use of this source code is governed by an MIT-style
+// license that can be found in the LICENSE file.
+
+//! Typed ITCH 5.0 messages.
+//!
+//! All integer fields are big-endian. Prices are fixed point: `Price(4)`
+//! fields have 4 implied decimal places and `Price(8)` fields have 8.
+//! Alpha fields are left justified and padded on the right with spaces.
+//!
+//! Each message type comes in two flavours: an owned struct such as
+//! [`AddOrder`], and a borrowed view such as [`AddOrderRef`] that wraps the
+//! frame bytes and decodes each field only when its accessor is called.
+//!
+//! Both are generated from one layout table per type at the bottom of this
+//! file, giving the name, kind and byte offset of each field and the length
+//! of the message. The tables also drive the length check of
+//! [`MessageRef::new`], the CSV headers and rows, and the columns of the
+//! other output formats, so a correction to the specification is a change
+//! to one table. Each table is checked at compile time to cover its message
+//! without gaps or overlaps.
+
+use std::fmt;
+
+/// The 22 message types in the ITCH 5.0 specification, in the order the
+/// specification lists them.
+pub const MESSAGE_TYPES: [u8; 22] = [
+    b'S', b'R', b'H', b'Y', b'L', b'V', b'W', b'K', b'J', b'h', b'A',
+    b'F', b'E', b'C', b'X', b'D', b'U', b'P', b'Q', b'B', b'I', b'N',
+];
+
+/// Position of message type `t` in [`MESSAGE_TYPES`], or `None` if `t` is
+/// not an ITCH 5.0 message type.
+pub fn message_type_index(t: u8) -> Option<usize> {
+    const NONE: u8 = u8::MAX;
+    const INDEX: [u8; 256] = {
+        let mut index = [NONE; 256];
+        let mut i = 0;
+        while i < MESSAGE_TYPES.len() {
+            index[MESSAGE_TYPES[i] as usize] = i as u8;
+            i += 1;
+        }
+        index
+    };
+    match INDEX[usize::from(t)] {
+        NONE => None,
+        i => Some(usize::from(i)),
+    }
+}
+
+/// Error returned by [`decode`] and [`MessageRef::new`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The message is zero bytes long, so it doesn't even have a type.
+    Empty,
+    /// The message type is not one of the 22 ITCH 5.0 types.
+    UnknownType(u8),
+    /// The message is of another type than the view it was wrapped in,
+    /// e.g. an `E` handed to [`AddOrderRef::new`].
+    WrongType { expected: u8, actual: u8 },
+    /// The message is shorter than the specification requires for its type.
+    Truncated {
+        message_type: u8,
+        expected: usize,
+        actual: usize,
+    },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            DecodeError::Empty => write!(f, "empty message"),
+            DecodeError::UnknownType(t) => {
+                write!(f, "unrecognized message type {:?}", t as char)
+            }
+            DecodeError::WrongType { expected, actual } => write!(
+                f,
+                "expected a {:?} message, got {:?}",
+                expected as char, actual as char
+            ),
+            DecodeError::Truncated { message_type, expected, actual } => write!(
+                f,
+                "{} message is {} bytes long, expected {}",
+                message_type as char, actual, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+#[inline]
+fn be_u16(m: &[u8], i: usize) -> u16 {
+    u16::from_be_bytes([m[i], m[i + 1]])
+}
+
+#[inline]
+fn be_u32(m: &[u8], i: usize) -> u32 {
+    u32::from_be_bytes([m[i], m[i + 1], m[i + 2], m[i + 3]])
+}
+
+#[inline]
+fn be_u48(m: &[u8], i: usize) -> u64 {
+    u64::from_be_bytes([0, 0, m[i], m[i + 1], m[i + 2], m[i + 3], m[i + 4], m[i + 5]])
+}
+
+#[inline]
+fn be_u64(m: &[u8], i: usize) -> u64 {
+    u64::from_be_bytes([
+        m[i], m[i + 1], m[i + 2], m[i + 3], m[i + 4], m[i + 5], m[i + 6], m[i + 7],
+    ])
+}
+
+#[inline]
+fn alpha<const N: usize>(m: &[u8], i: usize) -> &[u8; N] {
+    m[i..i + N].try_into().unwrap()
+}
+
+/// Displays an alpha field as text without allocating. ITCH alpha fields
+/// are printable ASCII, so anything else is shown lossily.
+#[derive(Debug, Clone, Copy)]
+pub struct Alpha<'a>(pub &'a [u8]);
+
+impl fmt::Display for Alpha<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match std::str::from_utf8(self.0) {
+            Ok(s) => f.write_str(s),
+            Err(_) => f.write_str(&String::from_utf8_lossy(self.0)),
+        }
+    }
+}
+
+/// Displays a nanoseconds-since-midnight timestamp as seconds with nine
+/// decimal places, e.g. `34200.000000123`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timestamp(pub u64);
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{:09}", self.0 / 1_000_000_000, self.0 % 1_000_000_000)
+    }
+}
+
+/// Displays a `Price(4)` field with its four implied decimal places.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Price4(pub u32);
+
+impl fmt::Display for Price4 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{:04}", self.0 / 10_000, self.0 % 10_000)
+    }
+}
+
+/// Displays a `Price(8)` field with its eight implied decimal places.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Price8(pub u64);
+
+impl fmt::Display for Price8 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{:08}", self.0 / 100_000_000, self.0 % 100_000_000)
+    }
+}
+
+/// How a message field is encoded on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldKind {
+    /// A single ASCII byte.
+    Char,
+    U16,
+    U32,
+    U64,
+    /// 48-bit nanoseconds since midnight.
+    Timestamp,
+    /// `Price(4)`: 32 bits with four implied decimal places.
+    Price4,
+    /// `Price(8)`: 64 bits with eight implied decimal places.
+    Price8,
+    /// Space-padded ASCII text of the given width.
+    Alpha(usize),
+}
+
+impl FieldKind {
+    /// Width of the field in bytes.
+    pub const fn width(self) -> usize {
+        match self {
+            FieldKind::Char => 1,
+            FieldKind::U16 => 2,
+            FieldKind::U32 | FieldKind::Price4 => 4,
+            FieldKind::Timestamp => 6,
+            FieldKind::U64 | FieldKind::Price8 => 8,
+            FieldKind::Alpha(n) => n,
+        }
+    }
+}
+
+/// One field of a message type, as listed in the specification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field {
+    /// Field name in snake case, e.g. `order_reference_number`.
+    pub name: &'static str,
+    pub kind: FieldKind,
+    /// Byte offset in the message, counting the type byte.
+    pub offset: usize,
+}
+
+// Whether `fields` cover a message of `length` bytes, each starting where
+// the previous one ends, after the type byte.
+const fn fields_cover(fields: &[Field], length: usize) -> bool {
+    let mut end = 1;
+    let mut i = 0;
+    while i < fields.len() {
+        if fields[i].offset != end {
+            return false;
+        }
+        end += fields[i].kind.width();
+        i += 1;
+    }
+    end == length
+}
+
+/// The value of one field of a decoded message, tagged with its kind.
+/// Displays the way the CSV output writes it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Value<'a> {
+    Char(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    Timestamp(u64),
+    Price4(u32),
+    Price8(u64),
+    Alpha(&'a [u8]),
+}
+
+impl fmt::Display for Value<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Value::Char(c) => write!(f, "{}", c as char),
+            Value::U16(v) => v.fmt(f),
+            Value::U32(v) => v.fmt(f),
+            Value::U64(v) => v.fmt(f),
+            Value::Timestamp(v) => Timestamp(v).fmt(f),
+            Value::Price4(v) => Price4(v).fmt(f),
+            Value::Price8(v) => Price8(v).fmt(f),
+            Value::Alpha(v) => Alpha(v).fmt(f),
+        }
+    }
+}
+
+// Rust type of each field kind used in the message tables below, as stored
+// in the owned message structs.
+macro_rules! field_type {
+    (char) => { u8 };
+    (u16) => { u16 };
+    (u32) => { u32 };
+    (u64) => { u64 };
+    (timestamp) => { u64 };
+    (price4) => { u32 };
+    (price8) => { u64 };
+    (alpha2) => { [u8; 2] };
+    (alpha4) => { [u8; 4] };
+    (alpha8) => { [u8; 8] };
+}
+
+// Return type of the accessor for each field kind on the borrowed views.
+// Alpha fields are handed out as references into the frame.
+macro_rules! ref_type {
+    ($a:lifetime, alpha2) => { &$a [u8; 2] };
+    ($a:lifetime, alpha4) => { &$a [u8; 4] };
+    ($a:lifetime, alpha8) => { &$a [u8; 8] };
+    ($a:lifetime, $kind:ident) => { field_type!($kind) };
+}
+
+// How each field kind is read out of the message bytes.
+macro_rules! read_field {
+    (char, $m:expr, $i:expr) => { $m[$i] };
+    (u16, $m:expr, $i:expr) => { be_u16($m, $i) };
+    (u32, $m:expr, $i:expr) => { be_u32($m, $i) };
+    (u64, $m:expr, $i:expr) => { be_u64($m, $i) };
+    (timestamp, $m:expr, $i:expr) => { be_u48($m, $i) };
+    (price4, $m:expr, $i:expr) => { be_u32($m, $i) };
+    (price8, $m:expr, $i:expr) => { be_u64($m, $i) };
+    (alpha2, $m:expr, $i:expr) => { alpha::<2>($m, $i) };
+    (alpha4, $m:expr, $i:expr) => { alpha::<4>($m, $i) };
+    (alpha8, $m:expr, $i:expr) => { alpha::<8>($m, $i) };
+}
+
+// The `FieldKind` of each field kind.
+macro_rules! field_kind {
+    (char) => { FieldKind::Char };
+    (u16) => { FieldKind::U16 };
+    (u32) => { FieldKind::U32 };
+    (u64) => { FieldKind::U64 };
+    (timestamp) => { FieldKind::Timestamp };
+    (price4) => { FieldKind::Price4 };
+    (price8) => { FieldKind::Price8 };
+    (alpha2) => { FieldKind::Alpha(2) };
+    (alpha4) => { FieldKind::Alpha(4) };
+    (alpha8) => { FieldKind::Alpha(8) };
+}
+
+// Wraps an owned field in a `Value`.
+macro_rules! field_value {
+    (char, $v:expr) => { Value::Char($v) };
+    (u16, $v:expr) => { Value::U16($v) };
+    (u32, $v:expr) => { Value::U32($v) };
+    (u64, $v:expr) => { Value::U64($v) };
+    (timestamp, $v:expr) => { Value::Timestamp($v) };
+    (price4, $v:expr) => { Value::Price4($v) };
+    (price8, $v:expr) => { Value::Price8($v) };
+    (alpha2, $v:expr) => { Value::Alpha(&$v) };
+    (alpha4, $v:expr) => { Value::Alpha(&$v) };
+    (alpha8, $v:expr) => { Value::Alpha(&$v) };
+}
+
+// Turns an accessor result into the owned field value.
+macro_rules! owned_field {
+    (alpha2, $v:expr) => { *$v };
+    (alpha4, $v:expr) => { *$v };
+    (alpha8, $v:expr) => { *$v };
+    ($kind:ident, $v:expr) => { $v };
+}
+
+// Defines, from the field table of each message type (name, kind and byte
+// offset of each field, and the message length), an owned struct, a
+// borrowed view over the frame bytes, the `Message` and `MessageRef` enums,
+// and `decode`. Each table is checked at compile time to cover its message.
+macro_rules! messages {
+    ($(
+        $(#[$doc:meta])*
+        $tag:literal => $name:ident / $view:ident, $len:literal {
+            $($field:ident: $kind:ident @ $off:literal,)*
+        }
+    )*) => {
+        $(
+            $(#[$doc])*
+            #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+            pub struct $name {
+                $(pub $field: field_type!($kind),)*
+            }
+
+            impl $name {
+                /// Message type byte.
+                pub const MESSAGE_TYPE: u8 = $tag;
+                /// Name of the message type, e.g. `AddOrder`.
+                pub const NAME: &'static str = stringify!($name);
+                /// Message length in bytes, excluding the length prefix.
+                pub const LENGTH: usize = $len;
+                /// Fields as in the specification, in wire order, leaving
+                /// out the message type.
+                pub const FIELDS: &'static [Field] = &[$(Field {
+                    name: stringify!($field),
+                    kind: field_kind!($kind),
+                    offset: $off,
+                }),*];
+
+                /// Calls `f` with each field and its value, in wire order.
+                pub fn for_each_field<F: FnMut(&'static Field, Value<'_>)>(&self, mut f: F) {
+                    let mut fields = Self::FIELDS.iter();
+                    $(f(fields.next().unwrap(), field_value!($kind, self.$field));)*
+                }
+            }
+
+            const _: () = assert!(
+                fields_cover($name::FIELDS, $len),
+                concat!("fields of ", stringify!($name), " don't cover its length"),
+            );
+
+            $(#[$doc])*
+            ///
+            /// Borrowed view over the raw message bytes. Fields are decoded
+            /// on access and nothing is copied or allocated.
+            #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+            pub struct $view<'a>(&'a [u8]);
+
+            impl<'a> $view<'a> {
+                /// Wraps the message bytes that follow the length prefix,
+                /// after checking the type byte and the length.
+                pub fn new(buf: &'a [u8]) -> Result<Self, DecodeError> {
+                    let t = *buf.first().ok_or(DecodeError::Empty)?;
+                    if t != $tag {
+                        return Err(match message_type_index(t) {
+                            Some(_) => DecodeError::WrongType { expected: $tag, actual: t },
+                            None => DecodeError::UnknownType(t),
+                        });
+                    }
+                    if buf.len() < $len {
+                        return Err(DecodeError::Truncated {
+                            message_type: t,
+                            expected: $len,
+                            actual: buf.len(),
+                        });
+                    }
+                    Ok($view(buf))
+                }
+
+                /// The underlying message bytes.
+                pub fn as_bytes(&self) -> &'a [u8] {
+                    self.0
+                }
+
+                $(
+                    #[inline]
+                    pub fn $field(&self) -> ref_type!('a, $kind) {
+                        read_field!($kind, self.0, $off)
+                    }
+                )*
+            }
+
+            impl From<$view<'_>> for $name {
+                fn from(v: $view<'_>) -> Self {
+                    $name { $($field: owned_field!($kind, v.$field()),)* }
+                }
+            }
+        )*
+
+        /// Name of message type `t`, e.g. `AddOrder` for `A`, or `None` for
+        /// an unknown type.
+        pub fn message_name(t: u8) -> Option<&'static str> {
+            match t {
+                $($tag => Some($name::NAME),)*
+                _ => None,
+            }
+        }
+
+        /// Fields of message type `t`, as in the specification and in wire
+        /// order, leaving out the message type. `None` for an unknown type.
+        pub fn message_fields(t: u8) -> Option<&'static [Field]> {
+            match t {
+                $($tag => Some($name::FIELDS),)*
+                _ => None,
+            }
+        }
+
+        /// A decoded ITCH 5.0 message.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum Message {
+            $($(#[$doc])* $name($name),)*
+        }
+
+        impl Message {
+            /// The message type byte, e.g. `b'A'` for Add Order.
+            pub fn message_type(&self) -> u8 {
+                match self {
+                    $(Message::$name(_) => $tag,)*
+                }
+            }
+
+            /// Locate code identifying the security; 0 for market-wide messages.
+            pub fn stock_locate(&self) -> u16 {
+                match self {
+                    $(Message::$name(m) => m.stock_locate,)*
+                }
+            }
+
+            /// Nasdaq internal tracking number.
+            pub fn tracking_number(&self) -> u16 {
+                match self {
+                    $(Message::$name(m) => m.tracking_number,)*
+                }
+            }
+
+            /// Nanoseconds since midnight.
+            pub fn timestamp(&self) -> u64 {
+                match self {
+                    $(Message::$name(m) => m.timestamp,)*
+                }
+            }
+
+            /// Calls `f` with each field and its value, in wire order,
+            /// leaving out the message type.
+            pub fn for_each_field<F: FnMut(&'static Field, Value<'_>)>(&self, f: F) {
+                match self {
+                    $(Message::$name(m) => m.for_each_field(f),)*
+                }
+            }
+        }
+
+        /// A borrowed, lazily decoded ITCH 5.0 message.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum MessageRef<'a> {
+            $($(#[$doc])* $name($view<'a>),)*
+        }
+
+        impl<'a> MessageRef<'a> {
+            /// Wraps one ITCH message. `buf` holds the message bytes that
+            /// follow the 2-byte length prefix, starting with the type byte.
+            /// Only the type and the length are checked here.
+            pub fn new(buf: &'a [u8]) -> Result<Self, DecodeError> {
+                let t = *buf.first().ok_or(DecodeError::Empty)?;
+                match t {
+                    $($tag => $view::new(buf).map(MessageRef::$name),)*
+                    _ => Err(DecodeError::UnknownType(t)),
+                }
+            }
+
+            /// The message type byte, e.g. `b'A'` for Add Order.
+            pub fn message_type(&self) -> u8 {
+                match self {
+                    $(MessageRef::$name(_) => $tag,)*
+                }
+            }
+
+            /// Locate code identifying the security; 0 for market-wide messages.
+            pub fn stock_locate(&self) -> u16 {
+                match self {
+                    $(MessageRef::$name(m) => m.stock_locate(),)*
+                }
+            }
+
+            /// Nasdaq internal tracking number.
+            pub fn tracking_number(&self) -> u16 {
+                match self {
+                    $(MessageRef::$name(m) => m.tracking_number(),)*
+                }
+            }
+
+            /// Nanoseconds since midnight.
+            pub fn timestamp(&self) -> u64 {
+                match self {
+                    $(MessageRef::$name(m) => m.timestamp(),)*
+                }
+            }
+
+            /// The underlying message bytes.
+            pub fn as_bytes(&self) -> &'a [u8] {
+                match self {
+                    $(MessageRef::$name(m) => m.as_bytes(),)*
+                }
+            }
+        }
+
+        impl From<MessageRef<'_>> for Message {
+            fn from(m: MessageRef<'_>) -> Self {
+                match m {
+                    $(MessageRef::$name(v) => Message::$name(v.into()),)*
+                }
+            }
+        }
+    };
+}
+
+/// Decodes one ITCH message into an owned [`Message`]. `buf` holds the
+/// message bytes that follow the 2-byte length prefix, starting with the
+/// type byte. Bytes beyond the length the specification defines are ignored.
+pub fn decode(buf: &[u8]) -> Result<Message, DecodeError> {
+    MessageRef::new(buf).map(Message::from)
+}
+
+messages! {
+    /// System Event Message (`S`).
+    b'S' => SystemEvent / SystemEventRef, 12 {
+        stock_locate: u16 @ 1,
+        tracking_number: u16 @ 3,
+        timestamp: timestamp @ 5,
+        event_code: char @ 11,
+    }
+
+    /// Stock Directory Message (`R`).
+    b'R' => StockDirectory / StockDirectoryRef, 39 {
+        stock_locate: u16 @ 1,
+        tracking_number: u16 @ 3,
+        timestamp: timestamp @ 5,
+        stock: alpha8 @ 11,
+        market_category: char @ 19,
+        financial_status_indicator: char @ 20,
+        round_lot_size: u32 @ 21,
+        round_lots_only: char @ 25,
+        issue_classification: char @ 26,
+        issue_sub_type: alpha2 @ 27,
+        authenticity: char @ 29,
+        short_sale_threshold_indicator: char @ 30,
+        ipo_flag: char @ 31,
+        luld_reference_price_tier: char @ 32,
+        etp_flag: char @ 33,
+        etp_leverage_factor: u32 @ 34,
+        inverse_indicator: char @ 38,
+    }
+
+    /// Stock Trading Action Message (`H`).
+    b'H' => StockTradingAction / StockTradingActionRef, 25 {
+        stock_locate: u16 @ 1,
+        tracking_number: u16 @ 3,
+        timestamp: timestamp @ 5,
+        stock: alpha8 @ 11,
+        trading_state: char @ 19,
+        reserved: char @ 20,
+        reason: alpha4 @ 21,
+    }
+
+    /// Reg SHO Short Sale Price Test Restricted Indicator Message (`Y`).
+    b'Y' => RegShoRestriction / RegShoRestrictionRef, 20 {
+        stock_locate: u16 @ 1,
+        tracking_number: u16 @ 3,
+        timestamp: timestamp @ 5,
+        stock: alpha8 @ 11,
+        reg_sho_action: char @ 19,
+    }
+
+    /// Market Participant Position Message (`L`).
+    b'L' => MarketParticipantPosition / MarketParticipantPositionRef, 26 {
+        stock_locate: u16 @ 1,
+        tracking_number: u16 @ 3,
+        timestamp: timestamp @ 5,
+        mpid: alpha4 @ 11,
+        stock: alpha8 @ 15,
+        primary_market_maker: char @ 23,
+        market_maker_mode: char @ 24,
+        market_participant_state: char @ 25,
+    }
+
+    /// MWCB Decline Level Message (`V`).
+    b'V' => MwcbDeclineLevel / MwcbDeclineLevelRef, 35 {
+        stock_locate: u16 @ 1,
+        tracking_number: u16 @ 3,
+        timestamp: timestamp @ 5,
+        level1: price8 @ 11,
+        level2: price8 @ 19,
+        level3: price8 @ 27,
+    }
+
+    /// MWCB Status Message (`W`).
+    b'W' => MwcbStatus / MwcbStatusRef, 12 {
+        stock_locate: u16 @ 1,
+        tracking_number: u16 @ 3,
+        timestamp: timestamp @ 5,
+        breached_level: char @ 11,
+    }
+
+    /// IPO Quoting Period Update Message (`K`).
+    b'K' => IpoQuotingPeriodUpdate / IpoQuotingPeriodUpdateRef, 28 {
+        stock_locate: u16 @ 1,
+        tracking_number: u16 @ 3,
+        timestamp: timestamp @ 5,
+        stock: alpha8 @ 11,
+        ipo_quotation_release_time: u32 @ 19,
+        ipo_quotation_release_qualifier: char @ 23,
+        ipo_price: price4 @ 24,
+    }
+
+    /// LULD Auction Collar Message (`J`).
+    b'J' => LuldAuctionCollar / LuldAuctionCollarRef, 35 {
+        stock_locate: u16 @ 1,
+        tracking_number: u16 @ 3,
+        timestamp: timestamp @ 5,
+        stock: alpha8 @ 11,
+        auction_collar_reference_price: price4 @ 19,
+        upper_auction_collar_price: price4 @ 23,
+        lower_auction_collar_price: price4 @ 27,
+        auction_collar_extension: u32 @ 31,
+    }
+
+    /// Operational Halt Message (`h`).
+    b'h' => OperationalHalt / OperationalHaltRef, 21 {
+        stock_locate: u16 @ 1,
+        tracking_number: u16 @ 3,
+        timestamp: timestamp @ 5,
+        stock: alpha8 @ 11,
+        market_code: char @ 19,
+        operational_halt_action: char @ 20,
+    }
+
+    /// Add Order – No MPID Attribution Message (`A`).
+    b'A' => AddOrder / AddOrderRef, 36 {
+        stock_locate: u16 @ 1,
+        tracking_number: u16 @ 3,
+        timestamp: timestamp @ 5,
+        order_reference_number: u64 @ 11,
+        buy_sell_indicator: char @ 19,
+        shares: u32 @ 20,
+        stock: alpha8 @ 24,
+        price: price4 @ 32,
+    }
+
+    /// Add Order with MPID Attribution Message (`F`).
+    b'F' => AddOrderMpid / AddOrderMpidRef, 40 {
+        stock_locate: u16 @ 1,
+        tracking_number: u16 @ 3,
+        timestamp: timestamp @ 5,
+        order_reference_number: u64 @ 11,
+        buy_sell_indicator: char @ 19,
+        shares: u32 @ 20,
+        stock: alpha8 @ 24,
+        price: price4 @ 32,
+        attribution: alpha4 @ 36,
+    }
+
+    /// Order Executed Message (`E`).
+    b'E' => OrderExecuted / OrderExecutedRef, 31 {
+        stock_locate: u16 @ 1,
+        tracking_number: u16 @ 3,
+        timestamp: timestamp @ 5,
+        order_reference_number: u64 @ 11,
+        executed_shares: u32 @ 19,
+        match_number: u64 @ 23,
+    }
+
+    /// Order Executed With Price Message (`C`).
+    b'C' => OrderExecutedWithPrice / OrderExecutedWithPriceRef, 36 {
+        stock_locate: u16 @ 1,
+        tracking_number: u16 @ 3,
+        timestamp: timestamp @ 5,
+        order_reference_number: u64 @ 11,
+        executed_shares: u32 @ 19,
+        match_number: u64 @ 23,
+        printable: char @ 31,
+        execution_price: price4 @ 32,
+    }
+
+    /// Order Cancel Message (`X`).
+    b'X' => OrderCancel / OrderCancelRef, 23 {
+        stock_locate: u16 @ 1,
+        tracking_number: u16 @ 3,
+        timestamp: timestamp @ 5,
+        order_reference_number: u64 @ 11,
+        cancelled_shares: u32 @ 19,
+    }
+
+    /// Order Delete Message (`D`).
+    b'D' => OrderDelete / OrderDeleteRef, 19 {
+        stock_locate: u16 @ 1,
+        tracking_number: u16 @ 3,
+        timestamp: timestamp @ 5,
+        order_reference_number: u64 @ 11,
+    }
+
+    /// Order Replace Message (`U`).
+    b'U' => OrderReplace / OrderReplaceRef, 35 {
+        stock_locate: u16 @ 1,
+        tracking_number: u16 @ 3,
+        timestamp: timestamp @ 5,
+        original_order_reference_number: u64 @ 11,
+        new_order_reference_number: u64 @ 19,
+        shares: u32 @ 27,
+        price: price4 @ 31,
+    }
+
+    /// Trade Message (Non-Cross) (`P`).
+    b'P' => Trade / TradeRef, 44 {
+        stock_locate: u16 @ 1,
+        tracking_number: u16 @ 3,
+        timestamp: timestamp @ 5,
+        order_reference_number: u64 @ 11,
+        buy_sell_indicator: char @ 19,
+        shares: u32 @ 20,
+        stock: alpha8 @ 24,
+        price: price4 @ 32,
+        match_number: u64 @ 36,
+    }
+
+    /// Cross Trade Message (`Q`).
+    b'Q' => CrossTrade / CrossTradeRef, 40 {
+        stock_locate: u16 @ 1,
+        tracking_number: u16 @ 3,
+        timestamp: timestamp @ 5,
+        shares: u64 @ 11,
+        stock: alpha8 @ 19,
+        cross_price: price4 @ 27,
+        match_number: u64 @ 31,
+        cross_type: char @ 39,
+    }
+
+    /// Broken Trade / Order Execution Message (`B`).
+    b'B' => BrokenTrade / BrokenTradeRef, 19 {
+        stock_locate: u16 @ 1,
+        tracking_number: u16 @ 3,
+        timestamp: timestamp @ 5,
+        match_number: u64 @ 11,
+    }
+
+    /// Net Order Imbalance Indicator (NOII) Message (`I`).
+    b'I' => NetOrderImbalance / NetOrderImbalanceRef, 50 {
+        stock_locate: u16 @ 1,
+        tracking_number: u16 @ 3,
+        timestamp: timestamp @ 5,
+        paired_shares: u64 @ 11,
+        imbalance_shares: u64 @ 19,
+        imbalance_direction: char @ 27,
+        stock: alpha8 @ 28,
+        far_price: price4 @ 36,
+        near_price: price4 @ 40,
+        current_reference_price: price4 @ 44,
+        cross_type: char @ 48,
+        price_variation_indicator: char @ 49,
+    }
+
+    /// Retail Price Improvement Indicator (RPII) Message (`N`).
+    b'N' => RetailPriceImprovement / RetailPriceImprovementRef, 20 {
+        stock_locate: u16 @ 1,
+        tracking_number: u16 @ 3,
+        timestamp: timestamp @ 5,
+        stock: alpha8 @ 11,
+        interest_flag: char @ 19,
+    }
+}
@@ -0,0 +1,46 @@
+// (C) Copyright 2020 Shawfeng Dong. All rights reserved.
+// Use of this source code is governed by an MIT-style
+// license that can be found in the LICENSE file.
+
+//! Memory-mapped input for multi-GB daily files.
+
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use crate::reader::SliceFrames;
+
+/// A read-only memory map of an ITCH file. Frames are slices of the
+/// mapping, so decoding through [`MessageRef`](crate::MessageRef) touches
+/// no memory besides the page cache.
+pub struct MappedFile {
+    map: Mmap,
+}
+
+impl MappedFile {
+    /// Maps the whole file at `path`.
+    ///
+    /// The file must not be truncated or rewritten while it is mapped;
+    /// the OS would then hand out changed bytes or raise SIGBUS. Daily ITCH
+    /// files are written once, which is why this is acceptable here.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: see the requirement above; the map is read-only.
+        let map = unsafe { Mmap::map(&file)? };
+        #[cfg(unix)]
+        map.advise(memmap2::Advice::Sequential)?;
+        Ok(MappedFile { map })
+    }
+
+    /// The mapped bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.map
+    }
+
+    /// Iterates over the frames of the file in order.
+    pub fn frames(&self) -> SliceFrames<'_> {
+        SliceFrames::new(&self.map)
+    }
+}
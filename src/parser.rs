@@ -0,0 +1,338 @@
+// (C) Copyright 2020 Shawfeng Dong. All rights reserved.
+// Use of this source code is governed by an MIT-style
+// license that can be found in the LICENSE file.
+
+//! The decode loop: frames in, messages out to a sink.
+
+use std::fmt;
+use std::io;
+
+use crate::message::{message_type_index, DecodeError, Message, MessageRef, MESSAGE_TYPES};
+use crate::reader::{FrameError, FrameSource};
+use crate::sink::{trim_alpha, Context, OutputSink};
+
+/// Drives frames from a [`FrameSource`] through the decoder into an
+/// [`OutputSink`]. Frames of unselected message types are skipped on their
+/// type byte, without being decoded.
+#[derive(Debug, Clone)]
+pub struct Parser {
+    selected: [bool; MESSAGE_TYPES.len()],
+    symbols: Option<Vec<String>>,
+    // time window, in nanoseconds since midnight: [start, end)
+    start: u64,
+    end: u64,
+    keep_state: bool,
+}
+
+/// Message types that build up state later messages depend on: the stock
+/// directory, trading actions and the order messages. See
+/// [`Parser::keep_state`].
+pub const STATE_TYPES: &[u8] = b"RHAFECXDU";
+
+/// Message counts from one [`Parser::run`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Number of frames read, including skipped ones.
+    pub frames: u64,
+    /// Number of messages handed to the sink.
+    pub total: u64,
+    /// Number of messages handed to the sink, per type, indexed like
+    /// [`MESSAGE_TYPES`].
+    pub by_type: [u64; MESSAGE_TYPES.len()],
+}
+
+/// Error that stops a [`Parser::run`].
+#[derive(Debug)]
+pub enum ParseError {
+    /// The input could not be split into frames.
+    Frame(FrameError),
+    /// A frame did not hold a valid message.
+    Decode { offset: u64, source: DecodeError },
+    /// The sink failed to write.
+    Output(io::Error),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Frame(e) => e.fmt(f),
+            ParseError::Decode { offset, source } => {
+                write!(f, "{} at byte offset {}", source, offset)
+            }
+            ParseError::Output(e) => write!(f, "output error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParseError::Frame(e) => Some(e),
+            ParseError::Decode { source, .. } => Some(source),
+            ParseError::Output(e) => Some(e),
+        }
+    }
+}
+
+impl From<FrameError> for ParseError {
+    fn from(e: FrameError) -> Self {
+        ParseError::Frame(e)
+    }
+}
+
+impl From<io::Error> for ParseError {
+    fn from(e: io::Error) -> Self {
+        ParseError::Output(e)
+    }
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Parser::new()
+    }
+}
+
+impl Parser {
+    /// A parser that passes every message type through.
+    pub fn new() -> Self {
+        Parser {
+            selected: [true; MESSAGE_TYPES.len()],
+            symbols: None,
+            start: 0,
+            end: u64::MAX,
+            keep_state: false,
+        }
+    }
+
+    /// Restricts the output to the message types in `types`, e.g. `b"RA"`.
+    /// Bytes that are not ITCH 5.0 message types are ignored.
+    pub fn only(mut self, types: &[u8]) -> Self {
+        self.selected = [false; MESSAGE_TYPES.len()];
+        for &t in types {
+            if let Some(i) = message_type_index(t) {
+                self.selected[i] = true;
+            }
+        }
+        self
+    }
+
+    /// Restricts the output to the stocks in `symbols`, e.g. `AAPL`, and
+    /// to market-wide messages (stock locate 0). Symbols match in any case,
+    /// so `aapl` keeps `AAPL`.
+    ///
+    /// Most messages only carry a stock locate code, so the codes of the
+    /// symbols are learned from the Stock Directory (`R`) messages as they
+    /// stream past, whether or not `R` is selected. Every other message,
+    /// including the order messages that refer to an order by reference
+    /// number only (`E`, `C`, `X`, `D` and `U`), is then kept or dropped on
+    /// its stock locate.
+    pub fn symbols<I, S>(mut self, symbols: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.symbols = Some(symbols.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Restricts the output to the messages timestamped at or after
+    /// `start` nanoseconds since midnight.
+    pub fn start_time(mut self, start: u64) -> Self {
+        self.start = start;
+        self
+    }
+
+    /// Restricts the output to the messages timestamped before `end`
+    /// nanoseconds since midnight.
+    pub fn end_time(mut self, end: u64) -> Self {
+        self.end = end;
+        self
+    }
+
+    /// Whether to keep the messages of [`STATE_TYPES`] from before the
+    /// start time, so that order books and symbol maps built downstream
+    /// are correct once the window opens. Off by default.
+    ///
+    /// The order messages that take shares off or remove an order are
+    /// kept along with `A`, `F` and `U`; otherwise orders filled or
+    /// cancelled before the window would still look live.
+    pub fn keep_state(mut self, on: bool) -> Self {
+        self.keep_state = on;
+        self
+    }
+
+    /// Whether messages of type `t` reach the sink.
+    pub fn is_selected(&self, t: u8) -> bool {
+        message_type_index(t).is_some_and(|i| self.selected[i])
+    }
+
+    /// Reads `frames` to the end, handing the selected messages to `sink`,
+    /// and finishes the sink.
+    ///
+    /// The sink is finished even if the input turns out to be truncated or
+    /// corrupt, so that the messages before the error are on disk; the
+    /// error is still returned.
+    pub fn run<F, S>(&self, frames: &mut F, sink: &mut S) -> Result<Stats, ParseError>
+    where
+        F: FrameSource + ?Sized,
+        S: OutputSink + ?Sized,
+    {
+        let result = self.feed(frames, sink);
+        let finished = sink.finish();
+        let stats = result?;
+        finished?;
+        Ok(stats)
+    }
+
+    fn feed<F, S>(&self, frames: &mut F, sink: &mut S) -> Result<Stats, ParseError>
+    where
+        F: FrameSource + ?Sized,
+        S: OutputSink + ?Sized,
+    {
+        let mut stats = Stats::default();
+        let mut filter = self.symbols.as_deref().map(SymbolFilter::new);
+        while let Some(frame) = frames.next_frame()? {
+            let ctx = Context { seq: stats.frames, offset: frame.offset };
+            stats.frames += 1;
+            // unknown types fall through so that decoding reports them
+            let index = frame.message_type().and_then(message_type_index);
+            let selected = index.is_none_or(|i| self.selected[i]);
+            // the symbol filter learns the locate codes from every R
+            let learn = filter.is_some() && frame.message_type() == Some(b'R');
+            if !(selected || learn) {
+                continue;
+            }
+            let view = match frame.message() {
+                Ok(m) => m,
+                Err(source) => return Err(ParseError::Decode { offset: frame.offset, source }),
+            };
+            if let Some(filter) = &mut filter {
+                if !filter.keep(&view) || !selected {
+                    continue;
+                }
+            }
+            if !self.in_window(&view) {
+                continue;
+            }
+            let msg: Message = view.into();
+            sink.on_message(&ctx, &msg)?;
+            stats.total += 1;
+            stats.by_type[index.unwrap()] += 1;
+        }
+        Ok(stats)
+    }
+
+    fn in_window(&self, msg: &MessageRef<'_>) -> bool {
+        let timestamp = msg.timestamp();
+        if timestamp < self.start {
+            self.keep_state && STATE_TYPES.contains(&msg.message_type())
+        } else {
+            timestamp < self.end
+        }
+    }
+}
+
+// Keeps the messages of a set of symbols, by stock locate.
+struct SymbolFilter<'a> {
+    symbols: &'a [String],
+    // indexed by stock locate
+    kept: Vec<bool>,
+}
+
+impl<'a> SymbolFilter<'a> {
+    fn new(symbols: &'a [String]) -> Self {
+        SymbolFilter { symbols, kept: Vec::new() }
+    }
+
+    fn keep(&mut self, msg: &MessageRef<'_>) -> bool {
+        let locate = msg.stock_locate();
+        let i = usize::from(locate);
+        if let MessageRef::StockDirectory(m) = msg {
+            let stock = trim_alpha(m.stock());
+            if i >= self.kept.len() {
+                self.kept.resize(i + 1, false);
+            }
+            self.kept[i] = self.symbols.iter().any(|s| s.as_bytes().eq_ignore_ascii_case(stock));
+        }
+        locate == 0 || self.kept.get(i) == Some(&true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::reader::SliceFrames;
+    use crate::sink::CsvSink;
+    use crate::testing::*;
+
+    // Keeps the messages it is given.
+    #[derive(Default)]
+    struct Collect(Vec<Message>);
+
+    impl OutputSink for Collect {
+        fn on_message(&mut self, _ctx: &Context, msg: &Message) -> io::Result<()> {
+            self.0.push(*msg);
+            Ok(())
+        }
+
+        fn paths(&self) -> &[PathBuf] {
+            &[]
+        }
+    }
+
+    fn run(parser: &Parser, msgs: &[Message]) -> Vec<Message> {
+        let input = feed(msgs);
+        let mut sink = Collect::default();
+        parser.run(&mut SliceFrames::new(&input), &mut sink).unwrap();
+        sink.0
+    }
+
+    #[test]
+    fn truncated_input_keeps_the_rows_before_the_cut() {
+        let folder = scratch_folder("truncated");
+        let mut input = feed(&[
+            add(1, 7, 1, b'B', 100, 1_000_000),
+            add(2, 7, 2, b'S', 50, 1_010_000),
+        ]);
+        input.truncate(input.len() - 5);
+        let mut sink = CsvSink::new(&folder, "feed");
+        let result = Parser::new().run(&mut SliceFrames::new(&input), &mut sink);
+        assert!(matches!(result, Err(ParseError::Frame(FrameError::TruncatedBody { .. }))));
+        let csv = fs::read_to_string(sink.path_for(b'A')).unwrap();
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(rows.len(), 2);
+        assert!(rows[1].starts_with("A,7,0,0.000000001,1,B,100,AAPL"), "{}", rows[1]);
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn symbols_are_learned_from_unselected_directory_messages() {
+        let msgs = [
+            system_event(1, b'O'),
+            directory(2, 1, "AAPL"),
+            directory(2, 2, "MSFT"),
+            add(3, 1, 1, b'B', 100, 1_000_000),
+            add(3, 2, 2, b'B', 100, 2_000_000),
+            execute(4, 1, 1, 10, 11),
+            execute(4, 2, 2, 10, 12),
+            execute_at(5, 2, 2, 10, 13, b'Y', 2_000_100),
+            cancel(6, 2, 2, 10),
+            replace(7, 2, 2, 3, 50, 2_000_200),
+            delete(8, 2, 3),
+            delete(8, 1, 1),
+        ];
+        let kept = run(&Parser::new().only(b"SAECXDU").symbols(["AAPL"]), &msgs);
+        // only the market-wide message and the order messages of locate 1
+        let expected = [0, 3, 5, 11].map(|i| msgs[i]);
+        assert_eq!(kept, expected);
+    }
+
+    #[test]
+    fn symbols_match_in_any_case() {
+        let msgs = [directory(1, 1, "AAPL"), add(2, 1, 1, b'B', 100, 1_000_000)];
+        assert_eq!(run(&Parser::new().symbols(["aapl"]), &msgs), msgs);
+    }
+}
@@ -0,0 +1,352 @@
+// (C) Copyright 2020 Shawfeng Dong. All rights reserved.
+// Use of this source code is governed by an MIT-style
+// license that can be found in the LICENSE file.
+
+//! Splitting a byte stream into length-prefixed ITCH frames.
+
+use std::fmt;
+use std::io::{self, BufReader, ErrorKind, Read};
+
+use crate::message::{DecodeError, MessageRef};
+
+/// Default size of the read buffer of [`ItchFrameReader`].
+pub const DEFAULT_READ_BUFFER: usize = 128 * 1024;
+
+/// One ITCH frame: the message bytes after the 2-byte length prefix,
+/// together with the byte offset of the prefix in the input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub offset: u64,
+    pub data: Vec<u8>,
+}
+
+impl Frame {
+    /// Borrows this frame as a [`FrameRef`].
+    pub fn as_frame_ref(&self) -> FrameRef<'_> {
+        FrameRef { offset: self.offset, data: &self.data }
+    }
+
+    /// Wraps the frame bytes in a lazily decoded message view.
+    pub fn message(&self) -> Result<MessageRef<'_>, DecodeError> {
+        MessageRef::new(&self.data)
+    }
+}
+
+/// Borrowed counterpart of [`Frame`], pointing into a reader's buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameRef<'a> {
+    pub offset: u64,
+    pub data: &'a [u8],
+}
+
+impl<'a> FrameRef<'a> {
+    /// The message type byte, or `None` for an empty frame.
+    pub fn message_type(&self) -> Option<u8> {
+        self.data.first().copied()
+    }
+
+    /// Wraps the frame bytes in a lazily decoded message view.
+    pub fn message(&self) -> Result<MessageRef<'a>, DecodeError> {
+        MessageRef::new(self.data)
+    }
+
+    /// Copies the frame out of the reader's buffer.
+    pub fn to_frame(&self) -> Frame {
+        Frame { offset: self.offset, data: self.data.to_vec() }
+    }
+}
+
+/// Error returned while splitting the input into frames. Each variant
+/// carries the byte offset of the frame it was reading.
+#[derive(Debug)]
+pub enum FrameError {
+    /// The underlying reader failed.
+    Io { offset: u64, source: io::Error },
+    /// The input ended after the first byte of a length prefix.
+    TruncatedLength { offset: u64 },
+    /// The input ended before the full message body was read.
+    TruncatedBody { offset: u64, expected: usize, actual: usize },
+}
+
+impl FrameError {
+    /// Byte offset of the length prefix of the offending frame.
+    pub fn offset(&self) -> u64 {
+        match *self {
+            FrameError::Io { offset, .. }
+            | FrameError::TruncatedLength { offset }
+            | FrameError::TruncatedBody { offset, .. } => offset,
+        }
+    }
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::Io { offset, source } => {
+                write!(f, "read error at byte offset {}: {}", offset, source)
+            }
+            FrameError::TruncatedLength { offset } => {
+                write!(f, "input ends inside the length prefix at byte offset {}", offset)
+            }
+            FrameError::TruncatedBody { offset, expected, actual } => write!(
+                f,
+                "input ends inside the frame at byte offset {}: expected {} bytes, got {}",
+                offset, expected, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FrameError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Anything that hands out ITCH frames one at a time. Frames borrow from
+/// the source, so each one must be dropped before asking for the next.
+pub trait FrameSource {
+    /// Returns the next frame, `Ok(None)` at a clean end of input.
+    fn next_frame(&mut self) -> Result<Option<FrameRef<'_>>, FrameError>;
+}
+
+/// Reads length-prefixed ITCH frames from any [`Read`] through a buffer.
+///
+/// Short reads are retried until the prefix or body is complete, so a pipe
+/// that hands out one byte at a time parses the same as a regular file.
+/// End of input is only clean when it falls exactly on a frame boundary.
+///
+/// [`next_frame`](Self::next_frame) reuses one internal buffer and does not
+/// allocate; the [`Iterator`] implementation copies each frame into an
+/// owned [`Frame`]. After an error the reader yields nothing more.
+pub struct ItchFrameReader<R> {
+    inner: BufReader<R>,
+    offset: u64,
+    buf: Vec<u8>,
+    done: bool,
+}
+
+impl<R: Read> ItchFrameReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self::with_capacity(DEFAULT_READ_BUFFER, inner)
+    }
+
+    /// Creates a reader whose read buffer holds `capacity` bytes.
+    pub fn with_capacity(capacity: usize, inner: R) -> Self {
+        ItchFrameReader {
+            inner: BufReader::with_capacity(capacity, inner),
+            offset: 0,
+            buf: Vec::new(),
+            done: false,
+        }
+    }
+
+    /// Number of bytes consumed so far, which is also the byte offset of
+    /// the next frame.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the next frame, `Ok(None)` at a clean end of input.
+    pub fn next_frame(&mut self) -> Result<Option<FrameRef<'_>>, FrameError> {
+        if self.done {
+            return Ok(None);
+        }
+        match self.read_frame() {
+            Ok(Some(offset)) => Ok(Some(FrameRef { offset, data: &self.buf })),
+            Ok(None) => {
+                self.done = true;
+                Ok(None)
+            }
+            Err(e) => {
+                self.done = true;
+                Err(e)
+            }
+        }
+    }
+
+    // Reads the next frame body into `self.buf` and returns its offset.
+    fn read_frame(&mut self) -> Result<Option<u64>, FrameError> {
+        let offset = self.offset;
+        let mut prefix = [0u8; 2];
+        match read_full(&mut self.inner, &mut prefix) {
+            Ok(0) => return Ok(None),
+            Ok(1) => return Err(FrameError::TruncatedLength { offset }),
+            Ok(_) => {}
+            Err(source) => return Err(FrameError::Io { offset, source }),
+        }
+
+        let length = usize::from(u16::from_be_bytes(prefix));
+        self.buf.resize(length, 0);
+        match read_full(&mut self.inner, &mut self.buf) {
+            Ok(n) if n == length => {}
+            Ok(actual) => {
+                return Err(FrameError::TruncatedBody { offset, expected: length, actual })
+            }
+            Err(source) => return Err(FrameError::Io { offset, source }),
+        }
+
+        self.offset += 2 + length as u64;
+        Ok(Some(offset))
+    }
+}
+
+impl<R: Read> Iterator for ItchFrameReader<R> {
+    type Item = Result<Frame, FrameError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_frame().map(|f| f.map(|f| f.to_frame())).transpose()
+    }
+}
+
+impl<R: Read> FrameSource for ItchFrameReader<R> {
+    fn next_frame(&mut self) -> Result<Option<FrameRef<'_>>, FrameError> {
+        ItchFrameReader::next_frame(self)
+    }
+}
+
+/// Splits ITCH frames out of a byte slice that holds a whole feed, such as
+/// a memory-mapped file. Frames point straight into the slice.
+#[derive(Debug, Clone)]
+pub struct SliceFrames<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    done: bool,
+}
+
+impl<'a> SliceFrames<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        SliceFrames { buf, pos: 0, done: false }
+    }
+
+    /// Byte offset of the next frame.
+    pub fn offset(&self) -> u64 {
+        self.pos as u64
+    }
+
+    fn read_frame(&mut self) -> Result<Option<FrameRef<'a>>, FrameError> {
+        let offset = self.pos as u64;
+        let rest = &self.buf[self.pos..];
+        match rest.len() {
+            0 => return Ok(None),
+            1 => return Err(FrameError::TruncatedLength { offset }),
+            _ => {}
+        }
+        let length = usize::from(u16::from_be_bytes([rest[0], rest[1]]));
+        let body = &rest[2..];
+        if body.len() < length {
+            return Err(FrameError::TruncatedBody { offset, expected: length, actual: body.len() });
+        }
+        self.pos += 2 + length;
+        Ok(Some(FrameRef { offset, data: &body[..length] }))
+    }
+}
+
+impl<'a> Iterator for SliceFrames<'a> {
+    type Item = Result<FrameRef<'a>, FrameError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let frame = self.read_frame();
+        if !matches!(frame, Ok(Some(_))) {
+            self.done = true;
+        }
+        frame.transpose()
+    }
+}
+
+impl FrameSource for SliceFrames<'_> {
+    fn next_frame(&mut self) -> Result<Option<FrameRef<'_>>, FrameError> {
+        self.next().transpose()
+    }
+}
+
+/// Fills `buf` from `r`, stopping early only at end of input. Returns the
+/// number of bytes read.
+fn read_full<R: Read>(r: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match r.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(k) => n += k,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Hands out one byte per read, like a slow pipe.
+    struct OneByte<'a>(&'a [u8]);
+
+    impl Read for OneByte<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match (self.0.split_first(), buf.first_mut()) {
+                (Some((&b, rest)), Some(out)) => {
+                    *out = b;
+                    self.0 = rest;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    // Two frames: a 3-byte one at offset 0 and a 1-byte one at offset 5.
+    const FEED: &[u8] = &[0, 3, b'D', 1, 2, 0, 1, b'S'];
+
+    #[test]
+    fn clean_eof() {
+        let mut frames = ItchFrameReader::new(OneByte(FEED));
+        let first = frames.next().unwrap().unwrap();
+        assert_eq!(first, Frame { offset: 0, data: vec![b'D', 1, 2] });
+        let second = frames.next().unwrap().unwrap();
+        assert_eq!(second, Frame { offset: 5, data: vec![b'S'] });
+        assert!(frames.next().is_none());
+        assert_eq!(frames.offset(), 8);
+    }
+
+    #[test]
+    fn eof_inside_length_prefix() {
+        let mut feed = FEED.to_vec();
+        feed.push(0);
+        let mut frames = ItchFrameReader::new(OneByte(&feed));
+        assert!(frames.next().unwrap().is_ok());
+        assert!(frames.next().unwrap().is_ok());
+        match frames.next() {
+            Some(Err(FrameError::TruncatedLength { offset })) => assert_eq!(offset, 8),
+            other => panic!("expected TruncatedLength, got {:?}", other),
+        }
+        assert!(frames.next().is_none());
+    }
+
+    #[test]
+    fn eof_inside_body() {
+        let mut frames = ItchFrameReader::new(OneByte(&FEED[..7]));
+        assert!(frames.next().unwrap().is_ok());
+        match frames.next() {
+            Some(Err(FrameError::TruncatedBody { offset, expected, actual })) => {
+                assert_eq!((offset, expected, actual), (5, 1, 0));
+            }
+            other => panic!("expected TruncatedBody, got {:?}", other),
+        }
+        assert!(frames.next().is_none());
+
+        let mut frames = ItchFrameReader::new(OneByte(&FEED[..4]));
+        match frames.next() {
+            Some(Err(FrameError::TruncatedBody { offset, expected, actual })) => {
+                assert_eq!((offset, expected, actual), (0, 3, 2));
+            }
+            other => panic!("expected TruncatedBody, got {:?}", other),
+        }
+    }
+}
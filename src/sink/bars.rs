@@ -0,0 +1,209 @@
+// (C) Copyright 2020 Shawfeng Dong. All rights reserved.
+// Use of this source code is governed by an MIT-style
+// license that can be found in the LICENSE file.
+
+//! Time bars (OHLCV) aggregated from the trade tape.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::PathBuf;
+
+use super::table::{Cell, ColumnType, Table, TableFormat};
+use super::trades::PrintLog;
+use super::{Context, OutputSink};
+use crate::message::Message;
+
+/// Default length of a bar: one minute.
+pub const DEFAULT_BAR_INTERVAL: u64 = 60_000_000_000;
+
+// One bar of one stock.
+struct Bar {
+    open: u32,
+    high: u32,
+    low: u32,
+    close: u32,
+    volume: u64,
+    // sum of Price(4) times shares
+    notional: u128,
+    trades: u64,
+    cross_volume: u64,
+}
+
+/// Aggregates the prints of the [`TradeTape`](crate::TradeTape) into one bar per stock and
+/// interval:
+///
+/// ```text
+/// timestamp,stock_locate,stock,open,high,low,close,volume,vwap,trade_count,cross_volume
+/// ```
+///
+/// `timestamp` is the start of the bar, a multiple of the interval since
+/// midnight. Crosses (`Q`) count like any other print, and their shares are
+/// also reported separately as `cross_volume`. Non-printable `C` prints,
+/// trades broken by a Broken Trade (`B`) message, `E` prints on orders
+/// added before the start of the input and prints of no shares, such as a
+/// `Q` for a cross that matched nothing, are left out. Intervals without
+/// trades have no bar.
+///
+/// As trades can be broken at any time, the prints are kept in memory and
+/// the bars written at the end of the input, ordered by time and then by
+/// stock locate.
+///
+/// The sink needs the same messages as [`TradesSink`](super::TradesSink).
+pub struct BarsSink {
+    table: Table,
+    interval: u64,
+    log: PrintLog,
+}
+
+impl BarsSink {
+    /// A sink writing bars of [`DEFAULT_BAR_INTERVAL`] to `path` in
+    /// `format`.
+    pub fn new<P: Into<PathBuf>>(path: P, format: TableFormat) -> Self {
+        let columns = [
+            ("timestamp", ColumnType::Timestamp),
+            ("stock_locate", ColumnType::U16),
+            ("stock", ColumnType::Symbol),
+            ("open", ColumnType::Price4),
+            ("high", ColumnType::Price4),
+            ("low", ColumnType::Price4),
+            ("close", ColumnType::Price4),
+            ("volume", ColumnType::U64),
+            ("vwap", ColumnType::F64),
+            ("trade_count", ColumnType::U64),
+            ("cross_volume", ColumnType::U64),
+        ];
+        let columns = columns.iter().map(|&(name, ty)| (name.to_string(), ty)).collect();
+        BarsSink {
+            table: Table::new(path.into(), format, columns),
+            interval: DEFAULT_BAR_INTERVAL,
+            log: PrintLog::new(),
+        }
+    }
+
+    /// Sets the length of a bar, in nanoseconds.
+    pub fn interval(mut self, nanos: u64) -> Self {
+        assert!(nanos > 0, "bar interval must be positive");
+        self.interval = nanos;
+        self
+    }
+
+    /// Only writes the bars that start at or after `start` and before
+    /// `end`; see [time windows](super#time-windows). Each bar written is
+    /// complete, even if its interval reaches past `end`.
+    pub fn window(mut self, start: u64, end: u64) -> Self {
+        self.table.set_window(start, end);
+        self
+    }
+
+    // Bars by start time and stock locate.
+    fn bars(&self) -> BTreeMap<(u64, u16), Bar> {
+        let mut bars = BTreeMap::new();
+        for p in &self.log.prints {
+            let counted = p.shares > 0 && p.printable && !self.log.is_broken(p.match_number);
+            let price = match p.price {
+                Some(price) if counted => price,
+                _ => continue,
+            };
+            let start = p.timestamp / self.interval * self.interval;
+            let bar = bars.entry((start, p.stock_locate)).or_insert(Bar {
+                open: price,
+                high: price,
+                low: price,
+                close: price,
+                volume: 0,
+                notional: 0,
+                trades: 0,
+                cross_volume: 0,
+            });
+            bar.high = bar.high.max(price);
+            bar.low = bar.low.min(price);
+            bar.close = price;
+            bar.volume += p.shares;
+            bar.notional += u128::from(price) * u128::from(p.shares);
+            bar.trades += 1;
+            if p.message_type == b'Q' {
+                bar.cross_volume += p.shares;
+            }
+        }
+        bars
+    }
+}
+
+impl OutputSink for BarsSink {
+    fn on_message(&mut self, _ctx: &Context, msg: &Message) -> io::Result<()> {
+        self.log.apply(msg);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        for ((start, locate), bar) in self.bars() {
+            let vwap = match bar.volume {
+                0 => Cell::Null,
+                volume => Cell::F64(bar.notional as f64 / volume as f64 / 10_000.0),
+            };
+            let cells = [
+                Cell::Timestamp(start),
+                Cell::U16(locate),
+                self.log.directory.symbol(locate).map_or(Cell::Null, Cell::Symbol),
+                Cell::Price4(bar.open),
+                Cell::Price4(bar.high),
+                Cell::Price4(bar.low),
+                Cell::Price4(bar.close),
+                Cell::U64(bar.volume),
+                vwap,
+                Cell::U64(bar.trades),
+                Cell::U64(bar.cross_volume),
+            ];
+            self.table.write_row(&cells)?;
+        }
+        self.table.finish()
+    }
+
+    fn paths(&self) -> &[PathBuf] {
+        self.table.paths()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::testing::*;
+
+    #[test]
+    fn aggregation() {
+        let folder = scratch_folder("bars");
+        let path = folder.join("bars.csv");
+        let mut sink = BarsSink::new(&path, TableFormat::Csv).interval(10);
+        let ctx = Context { seq: 0, offset: 0 };
+        let msgs = [
+            add(1, 7, 1, b'S', 1_000, 1_000_000),
+            execute(2, 7, 1, 100, 11),
+            execute_at(3, 7, 1, 50, 12, b'Y', 1_010_000),
+            // non-printable
+            execute_at(4, 7, 1, 70, 13, b'N', 1_020_000),
+            trade(5, 7, 200, 990_000, 14),
+            trade(5, 7, 300, 2_000_000, 15),
+            broken(6, 7, 15),
+            cross(7, 7, 400, 1_000_000, 16, b'C'),
+            // a cross that matched nothing
+            cross(8, 7, 0, 0, 17, b'C'),
+            execute(12, 7, 1, 10, 18),
+        ];
+        for msg in &msgs {
+            sink.on_message(&ctx, msg).unwrap();
+        }
+        sink.finish().unwrap();
+        // vwap: (100 * 100 + 50 * 101 + 200 * 99 + 400 * 100) / 750
+        let csv = fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            csv.lines().skip(1).collect::<Vec<_>>(),
+            [
+                "0.000000000,7,,100.0000,101.0000,99.0000,100.0000,750,99.8,4,400",
+                "0.000000010,7,,100.0000,100.0000,100.0000,100.0000,10,100,1,0",
+            ]
+        );
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
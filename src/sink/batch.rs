@@ -0,0 +1,293 @@
+// (C) Copyright 2020 Shawfeng Dong. All rights reserved.
+// Use of this source code is governed by an MIT-style
+// license that can be found in the LICENSE file.
+
+//! Arrow record batches, one schema per message type.
+//!
+//! Each message type has its own schema, with one column per field of the
+//! specification and no `message_type` column:
+//!
+//! | field kind | Arrow type                                          |
+//! |------------|-----------------------------------------------------|
+//! | char       | `Utf8`                                              |
+//! | integers   | `UInt16`, `UInt32`, `UInt64`                        |
+//! | timestamp  | `UInt64`, nanoseconds since midnight                |
+//! | Price(4)   | `Decimal128(10, 4)`                                 |
+//! | Price(8)   | `Decimal128(20, 8)`                                 |
+//! | alpha      | `Dictionary(Int32, Utf8)`, trailing spaces trimmed  |
+//!
+//! Alpha fields can also be plain `Utf8`, see [`BatchBuilder::dictionary`].
+
+use std::io;
+use std::sync::Arc;
+
+use arrow_array::builder::{
+    BooleanBuilder, Decimal128Builder, Float64Builder, StringBuilder, StringDictionaryBuilder,
+    UInt16Builder, UInt32Builder, UInt64Builder,
+};
+use arrow_array::types::Int32Type;
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field as ArrowField, Schema, SchemaRef};
+
+use super::{trim_alpha, Context, OutputSink};
+use crate::message::{message_fields, message_type_index, FieldKind, Message, Value, MESSAGE_TYPES};
+
+/// Default number of rows in a record batch.
+pub const DEFAULT_BATCH_SIZE: usize = 64 * 1024;
+
+/// Arrow data type of a field kind.
+fn data_type(kind: FieldKind, dictionary: bool) -> DataType {
+    match kind {
+        FieldKind::Char => DataType::Utf8,
+        FieldKind::U16 => DataType::UInt16,
+        FieldKind::U32 => DataType::UInt32,
+        FieldKind::U64 | FieldKind::Timestamp => DataType::UInt64,
+        FieldKind::Price4 => DataType::Decimal128(10, 4),
+        FieldKind::Price8 => DataType::Decimal128(20, 8),
+        FieldKind::Alpha(_) if dictionary => {
+            DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8))
+        }
+        FieldKind::Alpha(_) => DataType::Utf8,
+    }
+}
+
+/// Arrow schema of message type `t`, with dictionary-encoded alpha fields
+/// if `dictionary` is set. `None` for an unknown type.
+pub fn arrow_schema(t: u8, dictionary: bool) -> Option<SchemaRef> {
+    let fields = message_fields(t)?;
+    Some(Arc::new(Schema::new(
+        fields
+            .iter()
+            .map(|f| ArrowField::new(f.name, data_type(f.kind, dictionary), false))
+            .collect::<Vec<_>>(),
+    )))
+}
+
+// One column under construction, of any of the Arrow types written here
+// or by the derived tables.
+pub(crate) enum Column {
+    Text(StringBuilder),
+    U16(UInt16Builder),
+    U32(UInt32Builder),
+    U64(UInt64Builder),
+    F64(Float64Builder),
+    Bool(BooleanBuilder),
+    Decimal(Decimal128Builder),
+    Dictionary(StringDictionaryBuilder<Int32Type>),
+}
+
+impl Column {
+    pub(crate) fn new(data_type: &DataType) -> Self {
+        match *data_type {
+            DataType::Utf8 => Column::Text(StringBuilder::new()),
+            DataType::UInt16 => Column::U16(UInt16Builder::new()),
+            DataType::UInt32 => Column::U32(UInt32Builder::new()),
+            DataType::UInt64 => Column::U64(UInt64Builder::new()),
+            DataType::Float64 => Column::F64(Float64Builder::new()),
+            DataType::Boolean => Column::Bool(BooleanBuilder::new()),
+            DataType::Decimal128(p, s) => Column::Decimal(
+                Decimal128Builder::new()
+                    .with_precision_and_scale(p, s)
+                    .expect("price precision and scale are valid"),
+            ),
+            DataType::Dictionary(..) => Column::Dictionary(StringDictionaryBuilder::new()),
+            _ => unreachable!("no column is of type {}", data_type),
+        }
+    }
+
+    fn append(&mut self, value: Value<'_>) {
+        match (self, value) {
+            (Column::Text(b), Value::Char(c)) => {
+                b.append_value(char::from(c).encode_utf8(&mut [0; 4]))
+            }
+            (Column::Text(b), Value::Alpha(v)) => b.append_value(trim(v)),
+            (Column::U16(b), Value::U16(v)) => b.append_value(v),
+            (Column::U32(b), Value::U32(v)) => b.append_value(v),
+            (Column::U64(b), Value::U64(v) | Value::Timestamp(v)) => b.append_value(v),
+            (Column::Decimal(b), Value::Price4(v)) => b.append_value(i128::from(v)),
+            (Column::Decimal(b), Value::Price8(v)) => b.append_value(i128::from(v)),
+            (Column::Dictionary(b), Value::Alpha(v)) => b.append_value(trim(v)),
+            _ => unreachable!("column and value kinds match"),
+        }
+    }
+
+    pub(crate) fn append_null(&mut self) {
+        match self {
+            Column::Text(b) => b.append_null(),
+            Column::U16(b) => b.append_null(),
+            Column::U32(b) => b.append_null(),
+            Column::U64(b) => b.append_null(),
+            Column::F64(b) => b.append_null(),
+            Column::Bool(b) => b.append_null(),
+            Column::Decimal(b) => b.append_null(),
+            Column::Dictionary(b) => b.append_null(),
+        }
+    }
+
+    pub(crate) fn finish(&mut self) -> ArrayRef {
+        match self {
+            Column::Text(b) => Arc::new(b.finish()),
+            Column::U16(b) => Arc::new(b.finish()),
+            Column::U32(b) => Arc::new(b.finish()),
+            Column::U64(b) => Arc::new(b.finish()),
+            Column::F64(b) => Arc::new(b.finish()),
+            Column::Bool(b) => Arc::new(b.finish()),
+            Column::Decimal(b) => Arc::new(b.finish()),
+            Column::Dictionary(b) => Arc::new(b.finish()),
+        }
+    }
+}
+
+// An alpha field as text, without the space padding.
+fn trim(v: &[u8]) -> std::borrow::Cow<'_, str> {
+    String::from_utf8_lossy(trim_alpha(v))
+}
+
+/// Accumulates messages of one type into Arrow columns and hands them out
+/// as record batches.
+pub struct BatchBuilder {
+    message_type: u8,
+    schema: SchemaRef,
+    columns: Vec<Column>,
+    rows: usize,
+}
+
+impl BatchBuilder {
+    /// A builder for messages of type `t`, with dictionary-encoded alpha
+    /// fields. `None` for an unknown type.
+    pub fn new(t: u8) -> Option<Self> {
+        let fields = message_fields(t)?;
+        Some(BatchBuilder {
+            message_type: t,
+            schema: arrow_schema(t, true)?,
+            columns: fields.iter().map(|f| Column::new(&data_type(f.kind, true))).collect(),
+            rows: 0,
+        })
+    }
+
+    /// Whether alpha fields are dictionary encoded, which is the default,
+    /// or plain `Utf8`. Dictionaries differ from batch to batch, which the
+    /// Arrow IPC file format doesn't allow.
+    ///
+    /// # Panics
+    ///
+    /// If rows have already been appended.
+    pub fn dictionary(mut self, on: bool) -> Self {
+        assert!(self.is_empty(), "dictionary encoding is set before appending");
+        let fields = message_fields(self.message_type).unwrap();
+        self.schema = arrow_schema(self.message_type, on).unwrap();
+        self.columns = fields.iter().map(|f| Column::new(&data_type(f.kind, on))).collect();
+        self
+    }
+
+    /// Message type of the rows.
+    pub fn message_type(&self) -> u8 {
+        self.message_type
+    }
+
+    pub fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    /// Number of rows appended since the last [`finish`](Self::finish).
+    pub fn len(&self) -> usize {
+        self.rows
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows == 0
+    }
+
+    /// Appends `msg` as one row.
+    ///
+    /// # Panics
+    ///
+    /// If `msg` is not of the builder's message type.
+    pub fn append(&mut self, msg: &Message) {
+        assert_eq!(msg.message_type(), self.message_type, "message type of the batch");
+        let mut columns = self.columns.iter_mut();
+        msg.for_each_field(|_, value| columns.next().unwrap().append(value));
+        self.rows += 1;
+    }
+
+    /// Returns the rows appended so far as a batch and starts afresh.
+    pub fn finish(&mut self) -> RecordBatch {
+        let columns = self.columns.iter_mut().map(Column::finish).collect();
+        self.rows = 0;
+        RecordBatch::try_new(self.schema.clone(), columns).expect("columns match the schema")
+    }
+}
+
+/// Collects the messages in memory as Arrow record batches, per message
+/// type, for in-process consumers such as a Polars data frame.
+pub struct BatchSink {
+    batch_size: usize,
+    dictionary: bool,
+    builders: Vec<Option<BatchBuilder>>,
+    batches: Vec<Vec<RecordBatch>>,
+}
+
+impl Default for BatchSink {
+    fn default() -> Self {
+        BatchSink::new()
+    }
+}
+
+impl BatchSink {
+    pub fn new() -> Self {
+        BatchSink {
+            batch_size: DEFAULT_BATCH_SIZE,
+            dictionary: true,
+            builders: MESSAGE_TYPES.iter().map(|_| None).collect(),
+            batches: MESSAGE_TYPES.iter().map(|_| Vec::new()).collect(),
+        }
+    }
+
+    /// Sets the maximum number of rows in a batch.
+    pub fn batch_size(mut self, rows: usize) -> Self {
+        self.batch_size = rows;
+        self
+    }
+
+    /// Whether alpha fields are dictionary encoded, see
+    /// [`BatchBuilder::dictionary`].
+    pub fn dictionary(mut self, on: bool) -> Self {
+        self.dictionary = on;
+        self
+    }
+
+    /// Batches of message type `t` collected so far. The last rows only
+    /// show up once the sink is finished.
+    pub fn batches(&self, t: u8) -> &[RecordBatch] {
+        message_type_index(t).map_or(&[], |i| &self.batches[i])
+    }
+
+    /// Takes the batches of message type `t` out of the sink.
+    pub fn take_batches(&mut self, t: u8) -> Vec<RecordBatch> {
+        message_type_index(t).map_or_else(Vec::new, |i| std::mem::take(&mut self.batches[i]))
+    }
+}
+
+impl OutputSink for BatchSink {
+    fn on_message(&mut self, _ctx: &Context, msg: &Message) -> io::Result<()> {
+        let t = msg.message_type();
+        let i = message_type_index(t).expect("decoded messages have a known type");
+        let dictionary = self.dictionary;
+        let builder = self.builders[i]
+            .get_or_insert_with(|| BatchBuilder::new(t).unwrap().dictionary(dictionary));
+        builder.append(msg);
+        if builder.len() >= self.batch_size {
+            self.batches[i].push(builder.finish());
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        for (builder, batches) in self.builders.iter_mut().zip(&mut self.batches) {
+            if let Some(b) = builder.as_mut().filter(|b| !b.is_empty()) {
+                batches.push(b.finish());
+            }
+        }
+        Ok(())
+    }
+}
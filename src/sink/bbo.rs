@@ -0,0 +1,128 @@
+// (C) Copyright 2020 Shawfeng Dong. All rights reserved.
+// Use of this source code is governed by an MIT-style
+// license that can be found in the LICENSE file.
+
+//! Best bid and offer (BBO) changes.
+
+use std::io;
+use std::path::PathBuf;
+
+use super::table::{Cell, ColumnType, Table, TableFormat};
+use super::{Context, OutputSink};
+use crate::book::{Book, OrderBook};
+use crate::directory::Directory;
+use crate::message::Message;
+
+// Price(4) and shares at the top of one side.
+type Top = Option<(u32, u64)>;
+
+/// Rebuilds the order book and writes a row whenever the price or size of
+/// the best bid or the best ask of a stock changes:
+///
+/// ```text
+/// timestamp,stock_locate,stock,tracking_number,message_type,bid_px,bid_sz,ask_px,ask_sz
+/// ```
+///
+/// The timestamp, tracking number and message type are those of the
+/// message that caused the change. An empty side is left empty.
+///
+/// The sink needs the order messages (`A`, `F`, `E`, `C`, `X`, `D` and
+/// `U`) and, for the symbols, the Stock Directory messages (`R`).
+pub struct BboSink {
+    table: Table,
+    book: OrderBook,
+    directory: Directory,
+    // best bid and ask after the last change, indexed by stock locate
+    tops: Vec<(Top, Top)>,
+}
+
+impl BboSink {
+    pub fn new<P: Into<PathBuf>>(path: P, format: TableFormat) -> Self {
+        let columns = [
+            ("timestamp", ColumnType::Timestamp),
+            ("stock_locate", ColumnType::U16),
+            ("stock", ColumnType::Symbol),
+            ("tracking_number", ColumnType::U16),
+            ("message_type", ColumnType::Char),
+            ("bid_px", ColumnType::Price4),
+            ("bid_sz", ColumnType::U64),
+            ("ask_px", ColumnType::Price4),
+            ("ask_sz", ColumnType::U64),
+        ];
+        let columns = columns.iter().map(|&(name, ty)| (name.to_string(), ty)).collect();
+        BboSink {
+            table: Table::new(path.into(), format, columns),
+            book: OrderBook::new(),
+            directory: Directory::new(),
+            tops: Vec::new(),
+        }
+    }
+
+    /// Only writes the changes timestamped at or after `start` and before
+    /// `end`; see [time windows](super#time-windows). A stock whose top
+    /// doesn't change in the window has no rows.
+    pub fn window(mut self, start: u64, end: u64) -> Self {
+        self.table.set_window(start, end);
+        self
+    }
+
+    /// The order book as of the last message.
+    pub fn order_book(&self) -> &OrderBook {
+        &self.book
+    }
+
+    pub fn directory(&self) -> &Directory {
+        &self.directory
+    }
+}
+
+impl OutputSink for BboSink {
+    fn on_message(&mut self, _ctx: &Context, msg: &Message) -> io::Result<()> {
+        self.directory.apply(msg);
+        if !self.book.apply(msg) {
+            return Ok(());
+        }
+
+        let locate = msg.stock_locate();
+        let (bid, ask) = match self.book.book(locate) {
+            Some(book) => tops(book),
+            None => (None, None),
+        };
+        let i = usize::from(locate);
+        if i >= self.tops.len() {
+            self.tops.resize(i + 1, (None, None));
+        }
+        if self.tops[i] == (bid, ask) {
+            return Ok(());
+        }
+        self.tops[i] = (bid, ask);
+
+        let cells = [
+            Cell::Timestamp(msg.timestamp()),
+            Cell::U16(locate),
+            self.directory.symbol(locate).map_or(Cell::Null, Cell::Symbol),
+            Cell::U16(msg.tracking_number()),
+            Cell::Char(msg.message_type()),
+            bid.map_or(Cell::Null, |(price, _)| Cell::Price4(price)),
+            bid.map_or(Cell::Null, |(_, shares)| Cell::U64(shares)),
+            ask.map_or(Cell::Null, |(price, _)| Cell::Price4(price)),
+            ask.map_or(Cell::Null, |(_, shares)| Cell::U64(shares)),
+        ];
+        self.table.write_row(&cells)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.table.finish()
+    }
+
+    fn paths(&self) -> &[PathBuf] {
+        self.table.paths()
+    }
+}
+
+fn tops(book: &Book) -> (Top, Top) {
+    (
+        book.best_bid().map(|l| (l.price, l.shares)),
+        book.best_ask().map(|l| (l.price, l.shares)),
+    )
+}
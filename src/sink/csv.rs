@@ -0,0 +1,408 @@
+// (C) Copyright 2020 Shawfeng Dong. All rights reserved.
+// Use of this source code is governed by an MIT-style
+// license that can be found in the LICENSE file.
+
+//! One CSV file per message type, in the layout of the original parser.
+
+use std::fmt::{self, Write as _};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use super::{output_file_name, Context, Output, OutputSink, DEFAULT_WRITE_BUFFER};
+use crate::enrich::{enriched_fields, Enricher, Enrichment};
+use crate::message::*;
+
+/// Writes each message type to its own CSV file, `<base>-<type>.csv` in the
+/// output folder. Files are created on the first message of their type and
+/// written through a buffer of [`DEFAULT_WRITE_BUFFER`] bytes unless
+/// [`buffer_size`](Self::buffer_size) says otherwise.
+///
+/// Each file starts with a header row naming the columns after the fields
+/// of the specification (see [`csv_header`]), unless turned off with
+/// [`header`](Self::header).
+///
+/// With [`enrich`](Self::enrich), rows of the types listed in
+/// [`enriched_fields`] get extra columns at the end, looked up by an
+/// [`Enricher`].
+pub struct CsvSink {
+    folder: PathBuf,
+    base: String,
+    buffer_size: usize,
+    header: bool,
+    // indexed like MESSAGE_TYPES
+    selected: Vec<bool>,
+    enricher: Option<Enricher>,
+    files: Vec<Option<Output>>,
+    // indexed like MESSAGE_TYPES: files closed by close_files, to append to
+    closed: Vec<bool>,
+    paths: Vec<PathBuf>,
+    line: Vec<u8>,
+}
+
+impl CsvSink {
+    pub fn new<P: Into<PathBuf>, S: Into<String>>(folder: P, base: S) -> Self {
+        CsvSink {
+            folder: folder.into(),
+            base: base.into(),
+            buffer_size: DEFAULT_WRITE_BUFFER,
+            header: true,
+            selected: vec![true; MESSAGE_TYPES.len()],
+            enricher: None,
+            files: MESSAGE_TYPES.iter().map(|_| None).collect(),
+            closed: vec![false; MESSAGE_TYPES.len()],
+            paths: Vec::new(),
+            line: Vec::new(),
+        }
+    }
+
+    /// Sets the size in bytes of the write buffer of each file.
+    pub fn buffer_size(mut self, bytes: usize) -> Self {
+        self.buffer_size = bytes;
+        self
+    }
+
+    /// Whether to write a header row at the top of each file.
+    pub fn header(mut self, on: bool) -> Self {
+        self.header = on;
+        self
+    }
+
+    /// Only writes the message types in `types`, e.g. `b"EXD"`. Messages of
+    /// other types are still seen by the [`Enricher`].
+    pub fn only(mut self, types: &[u8]) -> Self {
+        for (selected, t) in self.selected.iter_mut().zip(MESSAGE_TYPES) {
+            *selected = types.contains(&t);
+        }
+        self
+    }
+
+    /// Whether to add the stock, side and price columns of
+    /// [`enriched_fields`]. The sink must then be given the `R` and order
+    /// messages, if only to look them up; see [`only`](Self::only).
+    pub fn enrich(mut self, on: bool) -> Self {
+        self.enricher = if on { Some(Enricher::new()) } else { None };
+        self
+    }
+
+    /// Path of the CSV file for message type `t`.
+    pub fn path_for(&self, t: u8) -> PathBuf {
+        self.folder.join(csv_file_name(&self.base, t))
+    }
+}
+
+impl OutputSink for CsvSink {
+    fn on_message(&mut self, _ctx: &Context, msg: &Message) -> io::Result<()> {
+        let t = msg.message_type();
+        let i = message_type_index(t).expect("decoded messages have a known type");
+        let enrichment = self.enricher.as_mut().map(|e| e.apply(msg));
+        if !self.selected[i] {
+            return Ok(());
+        }
+        if self.files[i].is_none() && self.closed[i] {
+            let path = self.folder.join(csv_file_name(&self.base, t));
+            self.files[i] = Some(Output::append(path, self.buffer_size)?);
+        }
+        if self.files[i].is_none() {
+            let path = self.folder.join(csv_file_name(&self.base, t));
+            let mut out = Output::create(path.clone(), self.buffer_size)?;
+            if self.header {
+                let mut header = csv_header(t);
+                if enrichment.is_some() {
+                    for name in enriched_fields(t) {
+                        header.push(',');
+                        header.push_str(name);
+                    }
+                }
+                writeln!(out.writer, "{}", header).map_err(|e| out.error(e))?;
+            }
+            self.files[i] = Some(out);
+            self.paths.push(path);
+        }
+        let out = self.files[i].as_mut().unwrap();
+        match enrichment {
+            None => write_row(&mut out.writer, msg).map_err(|e| out.error(e)),
+            Some(enrichment) => {
+                // the extra columns go before the line break
+                self.line.clear();
+                write_row(&mut self.line, msg)?;
+                self.line.pop();
+                write_enrichment(&mut self.line, t, &enrichment)?;
+                self.line.push(b'\n');
+                out.writer.write_all(&self.line).map_err(|e| out.error(e))
+            }
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        for out in self.files.iter_mut().flatten() {
+            out.writer.flush().map_err(|e| out.error(e))?;
+        }
+        Ok(())
+    }
+
+    fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    // The files are reopened in append mode on the next message of their
+    // type, without a second header.
+    fn close_files(&mut self) -> io::Result<()> {
+        for (file, closed) in self.files.iter_mut().zip(&mut self.closed) {
+            if let Some(mut out) = file.take() {
+                out.writer.flush().map_err(|e| out.error(e))?;
+                *closed = true;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes all messages to one CSV file in feed order, each row led by the
+/// `seq` and `offset` of its frame in the input (see [`Context`]) and the
+/// message type.
+///
+/// The columns after `message_type` are the union of the fields of all
+/// message types, named after the specification; each row fills in the
+/// fields of its type and leaves the others empty. Values are written as in
+/// [`CsvSink`].
+pub struct InterleavedCsvSink {
+    path: PathBuf,
+    buffer_size: usize,
+    header: bool,
+    out: Option<Output>,
+    // for each message type, the column of each of its fields
+    columns: Vec<Vec<usize>>,
+    names: Vec<&'static str>,
+    cells: Vec<String>,
+    paths: Vec<PathBuf>,
+}
+
+impl InterleavedCsvSink {
+    /// A sink writing to `path`, which is created on the first message.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        let mut names: Vec<&'static str> = Vec::new();
+        let mut columns = Vec::new();
+        for &t in &MESSAGE_TYPES {
+            let fields = message_fields(t).unwrap();
+            columns.push(
+                fields
+                    .iter()
+                    .map(|f| match names.iter().position(|&n| n == f.name) {
+                        Some(i) => i,
+                        None => {
+                            names.push(f.name);
+                            names.len() - 1
+                        }
+                    })
+                    .collect(),
+            );
+        }
+        InterleavedCsvSink {
+            path: path.into(),
+            buffer_size: DEFAULT_WRITE_BUFFER,
+            header: true,
+            out: None,
+            columns,
+            cells: vec![String::new(); names.len()],
+            names,
+            paths: Vec::new(),
+        }
+    }
+
+    /// Sets the size in bytes of the write buffer.
+    pub fn buffer_size(mut self, bytes: usize) -> Self {
+        self.buffer_size = bytes;
+        self
+    }
+
+    /// Whether to write a header row at the top of the file.
+    pub fn header(mut self, on: bool) -> Self {
+        self.header = on;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The header row: `seq,offset,message_type` and the field names.
+    pub fn header_row(&self) -> String {
+        let mut header = String::from("seq,offset,message_type");
+        for name in &self.names {
+            header.push(',');
+            header.push_str(name);
+        }
+        header
+    }
+}
+
+impl OutputSink for InterleavedCsvSink {
+    fn on_message(&mut self, ctx: &Context, msg: &Message) -> io::Result<()> {
+        if self.out.is_none() {
+            let mut out = Output::create(self.path.clone(), self.buffer_size)?;
+            if self.header {
+                writeln!(out.writer, "{}", self.header_row()).map_err(|e| out.error(e))?;
+            }
+            self.out = Some(out);
+            self.paths.push(self.path.clone());
+        }
+        let t = msg.message_type();
+        let columns = &self.columns[message_type_index(t).expect("decoded messages have a known type")];
+        for cell in &mut self.cells {
+            cell.clear();
+        }
+        let mut i = 0;
+        msg.for_each_field(|_, value| {
+            write!(self.cells[columns[i]], "{}", value).unwrap();
+            i += 1;
+        });
+
+        let out = self.out.as_mut().unwrap();
+        let w = &mut out.writer;
+        let row = write!(w, "{},{},{}", ctx.seq, ctx.offset, t as char)
+            .and_then(|_| self.cells.iter().try_for_each(|cell| write!(w, ",{}", cell)))
+            .and_then(|_| writeln!(w));
+        row.map_err(|e| out.error(e))
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        match &mut self.out {
+            Some(out) => out.writer.flush().map_err(|e| out.error(e)),
+            None => Ok(()),
+        }
+    }
+
+    fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+}
+
+/// File name of the CSV file for message type `t`, e.g. `S051018-v50-A.csv`.
+pub fn csv_file_name(base: &str, t: u8) -> String {
+    output_file_name(base, t, "csv")
+}
+
+/// Header row of the CSV file for message type `t`: `message_type`
+/// followed by the field names of the specification, e.g.
+/// `message_type,stock_locate,tracking_number,timestamp,event_code` for
+/// `S`.
+pub fn csv_header(t: u8) -> String {
+    let mut header = String::from("message_type");
+    for field in message_fields(t).unwrap_or_default() {
+        header.push(',');
+        header.push_str(field.name);
+    }
+    header
+}
+
+// Writes the enriched columns of message type `t`, each led by a comma.
+fn write_enrichment<W: Write>(w: &mut W, t: u8, e: &Enrichment<'_>) -> io::Result<()> {
+    for &name in enriched_fields(t) {
+        match name {
+            "stock" => write!(w, ",{}", e.stock.unwrap_or(""))?,
+            "side" => match e.side {
+                Some(side) => write!(w, ",{}", side.indicator() as char)?,
+                None => write!(w, ",")?,
+            },
+            _ => match e.price {
+                Some(price) => write!(w, ",{}", Price4(price))?,
+                None => write!(w, ",")?,
+            },
+        }
+    }
+    Ok(())
+}
+
+/// Writes `msg` as one CSV line. Fields appear in specification order,
+/// led by the message type, each written as its [`Value`] displays.
+pub(crate) fn write_row<W: Write>(w: &mut W, msg: &Message) -> io::Result<()> {
+    writeln!(w, "{}", Row(msg))
+}
+
+// Displays a message as a CSV line without the line break, so that the
+// whole line goes through one formatter.
+struct Row<'a>(&'a Message);
+
+impl fmt::Display for Row<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.message_type() as char)?;
+        let mut row = Ok(());
+        self.0.for_each_field(|_, value| {
+            if row.is_ok() {
+                row = write!(f, ",{}", value);
+            }
+        });
+        row
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Frames laid out by hand from the specification, not from the tables.
+    fn add_order() -> Vec<u8> {
+        let mut m = vec![b'A'];
+        m.extend(7u16.to_be_bytes());
+        m.extend(0u16.to_be_bytes());
+        m.extend(&34_200_000_000_123u64.to_be_bytes()[2..]);
+        m.extend(42u64.to_be_bytes());
+        m.push(b'B');
+        m.extend(100u32.to_be_bytes());
+        // all 8 bytes of the stock are kept
+        m.extend(b"ABCDEFGH");
+        m.extend(1_234_500u32.to_be_bytes());
+        m
+    }
+
+    fn broken_trade() -> Vec<u8> {
+        let mut m = vec![b'B'];
+        m.extend(7u16.to_be_bytes());
+        m.extend(1u16.to_be_bytes());
+        m.extend(&34_200_000_000_005u64.to_be_bytes()[2..]);
+        m.extend(9001u64.to_be_bytes());
+        m
+    }
+
+    fn row(m: &[u8]) -> String {
+        let mut line = Vec::new();
+        write_row(&mut line, &decode(m).unwrap()).unwrap();
+        String::from_utf8(line).unwrap()
+    }
+
+    #[test]
+    fn rows() {
+        assert_eq!(row(&add_order()), "A,7,0,34200.000000123,42,B,100,ABCDEFGH,123.4500\n");
+        // nanoseconds are padded to nine digits
+        assert_eq!(row(&broken_trade()), "B,7,1,34200.000000005,9001\n");
+    }
+
+    #[test]
+    fn headers() {
+        assert_eq!(
+            csv_header(b'A'),
+            "message_type,stock_locate,tracking_number,timestamp,order_reference_number,\
+             buy_sell_indicator,shares,stock,price"
+        );
+        assert_eq!(
+            csv_header(b'B'),
+            "message_type,stock_locate,tracking_number,timestamp,match_number"
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        let add = add_order();
+        assert_eq!(add.len(), 36);
+        assert_eq!(
+            decode(&add[..35]).unwrap_err(),
+            DecodeError::Truncated { message_type: b'A', expected: 36, actual: 35 }
+        );
+        let broken = broken_trade();
+        assert_eq!(broken.len(), 19);
+        assert_eq!(
+            decode(&broken[..18]).unwrap_err(),
+            DecodeError::Truncated { message_type: b'B', expected: 19, actual: 18 }
+        );
+    }
+}
@@ -0,0 +1,116 @@
+// (C) Copyright 2020 Shawfeng Dong. All rights reserved.
+// Use of this source code is governed by an MIT-style
+// license that can be found in the LICENSE file.
+
+//! One Arrow IPC (Feather v2) file per message type.
+
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::PathBuf;
+
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::ArrowError;
+
+use super::batch::{BatchBuilder, DEFAULT_BATCH_SIZE};
+use super::{output_file_name, Context, Output, OutputSink, DEFAULT_WRITE_BUFFER};
+use crate::message::*;
+
+/// Writes each message type to its own Arrow IPC file, also known as
+/// Feather v2, `<base>-<type>.arrow` in the output folder. Files are
+/// created on the first message of their type.
+///
+/// The files are uncompressed so that readers such as Polars can memory-map
+/// them. The columns are those of [`BatchBuilder`], except that alpha
+/// fields are plain strings: an IPC file can't carry a different
+/// dictionary in every batch.
+pub struct ArrowSink {
+    folder: PathBuf,
+    base: String,
+    batch_size: usize,
+    files: Vec<Option<ArrowFile>>,
+    paths: Vec<PathBuf>,
+}
+
+struct ArrowFile {
+    path: PathBuf,
+    batch: BatchBuilder,
+    writer: FileWriter<BufWriter<File>>,
+}
+
+impl ArrowFile {
+    // Appends the collected rows to the file as one record batch.
+    fn write_batch(&mut self) -> io::Result<()> {
+        let batch = self.batch.finish();
+        self.writer.write(&batch).map_err(|e| self.error(e))
+    }
+
+    fn error(&self, e: ArrowError) -> io::Error {
+        super::with_path(io::Error::other(e), "can't write to", &self.path)
+    }
+}
+
+impl ArrowSink {
+    pub fn new<P: Into<PathBuf>, S: Into<String>>(folder: P, base: S) -> Self {
+        ArrowSink {
+            folder: folder.into(),
+            base: base.into(),
+            batch_size: DEFAULT_BATCH_SIZE,
+            files: MESSAGE_TYPES.iter().map(|_| None).collect(),
+            paths: Vec::new(),
+        }
+    }
+
+    /// Sets the maximum number of rows in a record batch.
+    pub fn batch_size(mut self, rows: usize) -> Self {
+        self.batch_size = rows;
+        self
+    }
+
+    /// Path of the Arrow file for message type `t`.
+    pub fn path_for(&self, t: u8) -> PathBuf {
+        self.folder.join(output_file_name(&self.base, t, "arrow"))
+    }
+
+    fn create(&self, t: u8) -> io::Result<ArrowFile> {
+        let Output { path, writer } = Output::create(self.path_for(t), DEFAULT_WRITE_BUFFER)?;
+        let batch = BatchBuilder::new(t)
+            .expect("decoded messages have a known type")
+            .dictionary(false);
+        match FileWriter::try_new(writer, &batch.schema()) {
+            Ok(writer) => Ok(ArrowFile { path, batch, writer }),
+            Err(e) => Err(super::with_path(io::Error::other(e), "can't create", &path)),
+        }
+    }
+}
+
+impl OutputSink for ArrowSink {
+    fn on_message(&mut self, _ctx: &Context, msg: &Message) -> io::Result<()> {
+        let t = msg.message_type();
+        let i = message_type_index(t).expect("decoded messages have a known type");
+        if self.files[i].is_none() {
+            let file = self.create(t)?;
+            self.paths.push(file.path.clone());
+            self.files[i] = Some(file);
+        }
+        let file = self.files[i].as_mut().unwrap();
+        file.batch.append(msg);
+        if file.batch.len() >= self.batch_size {
+            file.write_batch()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        for file in self.files.iter_mut().flatten() {
+            if !file.batch.is_empty() {
+                file.write_batch()?;
+            }
+            file.writer.finish().map_err(|e| file.error(e))?;
+        }
+        Ok(())
+    }
+
+    fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+}
@@ -0,0 +1,116 @@
+// (C) Copyright 2020 Shawfeng Dong. All rights reserved.
+// Use of this source code is governed by an MIT-style
+// license that can be found in the LICENSE file.
+
+//! All messages in one JSON Lines file, in feed order.
+
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use super::{trim_alpha, Context, Output, OutputSink, DEFAULT_WRITE_BUFFER};
+use crate::message::*;
+
+/// Writes every message as one JSON object per line, in feed order, to a
+/// single file. Each object starts with the message `type`, followed by
+/// the fields of the specification:
+///
+/// ```text
+/// {"type":"A","stock_locate":4,"tracking_number":3,"timestamp":30601081329274,...}
+/// ```
+///
+/// Timestamps are nanoseconds since midnight, prices are numbers with
+/// their implied decimal places, and characters and alpha fields are
+/// strings, the latter without the space padding.
+pub struct JsonlSink {
+    path: PathBuf,
+    buffer_size: usize,
+    out: Option<Output>,
+    line: String,
+    paths: Vec<PathBuf>,
+}
+
+impl JsonlSink {
+    /// A sink writing to `path`, which is created on the first message.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        JsonlSink {
+            path: path.into(),
+            buffer_size: DEFAULT_WRITE_BUFFER,
+            out: None,
+            line: String::new(),
+            paths: Vec::new(),
+        }
+    }
+
+    /// Sets the size in bytes of the write buffer.
+    pub fn buffer_size(mut self, bytes: usize) -> Self {
+        self.buffer_size = bytes;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl OutputSink for JsonlSink {
+    fn on_message(&mut self, _ctx: &Context, msg: &Message) -> io::Result<()> {
+        if self.out.is_none() {
+            self.out = Some(Output::create(self.path.clone(), self.buffer_size)?);
+            self.paths.push(self.path.clone());
+        }
+        let out = self.out.as_mut().unwrap();
+        self.line.clear();
+        write_json(&mut self.line, msg);
+        self.line.push('\n');
+        out.writer.write_all(self.line.as_bytes()).map_err(|e| out.error(e))
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        match &mut self.out {
+            Some(out) => out.writer.flush().map_err(|e| out.error(e)),
+            None => Ok(()),
+        }
+    }
+
+    fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+}
+
+/// Appends `msg` to `s` as one JSON object, without a line break.
+pub fn write_json(s: &mut String, msg: &Message) {
+    s.push_str("{\"type\":");
+    write_string(s, &[msg.message_type()]);
+    msg.for_each_field(|field, value| {
+        s.push_str(",\"");
+        s.push_str(field.name);
+        s.push_str("\":");
+        match value {
+            Value::Char(c) => write_string(s, &[c]),
+            Value::Alpha(v) => write_string(s, trim_alpha(v)),
+            Value::Timestamp(ns) => write!(s, "{}", ns).unwrap(),
+            // the remaining kinds display as JSON numbers
+            _ => write!(s, "{}", value).unwrap(),
+        }
+    });
+    s.push('}');
+}
+
+// Appends `v` as a JSON string. ITCH text is ASCII; other bytes are
+// replaced rather than producing invalid UTF-8.
+fn write_string(s: &mut String, v: &[u8]) {
+    s.push('"');
+    for c in String::from_utf8_lossy(v).chars() {
+        match c {
+            '"' => s.push_str("\\\""),
+            '\\' => s.push_str("\\\\"),
+            '\n' => s.push_str("\\n"),
+            '\r' => s.push_str("\\r"),
+            '\t' => s.push_str("\\t"),
+            c if c < ' ' => write!(s, "\\u{:04x}", c as u32).unwrap(),
+            c => s.push(c),
+        }
+    }
+    s.push('"');
+}
@@ -0,0 +1,179 @@
+// (C) Copyright 2020 Shawfeng Dong. All rights reserved.
+// Use of this source code is governed by an MIT-style
+// license that can be found in the LICENSE file.
+
+//! Output sinks: where decoded messages go.
+//!
+//! The [`Parser`](crate::Parser) hands every selected message to an
+//! [`OutputSink`]. New output formats, and in-process consumers such as an
+//! order book, plug in by implementing the trait; the decode loop itself
+//! does not change.
+//!
+//! # Time windows
+//!
+//! The sinks of derived tables, [`SnapshotSink`], [`BboSink`],
+//! [`TradesSink`] and [`BarsSink`], have a `window(start, end)` setting,
+//! in nanoseconds since midnight, that drops the rows timestamped before
+//! `start` or at or after `end`. Unlike a time window on the
+//! [`Parser`](crate::Parser), it leaves the messages alone: the sinks still
+//! build the book, the tape and the symbols from the whole input, so rows
+//! in the window reflect the orders resting before it, `E` prints are
+//! priced from orders added before it, and trades broken after it are
+//! marked as such.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+
+use crate::message::Message;
+
+mod bars;
+mod batch;
+mod bbo;
+mod csv;
+mod ipc;
+mod jsonl;
+mod parquet;
+mod partition;
+mod snapshot;
+mod sqlite;
+mod table;
+mod trades;
+
+pub use self::bars::{BarsSink, DEFAULT_BAR_INTERVAL};
+pub use self::batch::{arrow_schema, BatchBuilder, BatchSink, DEFAULT_BATCH_SIZE};
+pub use self::bbo::BboSink;
+pub use self::csv::{csv_file_name, csv_header, CsvSink, InterleavedCsvSink};
+pub use self::ipc::ArrowSink;
+pub use self::jsonl::{write_json, JsonlSink};
+pub use self::parquet::{ParquetSink, DEFAULT_ROW_GROUP_SIZE};
+pub use self::partition::{
+    PartitionedSink, Partitioning, DEFAULT_BUCKETS, DEFAULT_OPEN_PARTITIONS, PARTITION_WRITE_BUFFER,
+};
+pub use self::snapshot::{SnapshotSink, Trigger, DEFAULT_DEPTH};
+pub use self::sqlite::{table_name, SqliteSink};
+pub use self::table::TableFormat;
+pub use self::trades::TradesSink;
+
+/// Default size of the write buffer of each output file.
+pub const DEFAULT_WRITE_BUFFER: usize = 256 * 1024;
+
+/// Where a message sits in the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Context {
+    /// 0-based index of the frame in the input, counting skipped frames.
+    pub seq: u64,
+    /// Byte offset of the frame's length prefix in the input.
+    pub offset: u64,
+}
+
+/// Receives decoded messages in feed order.
+pub trait OutputSink {
+    fn on_message(&mut self, ctx: &Context, msg: &Message) -> io::Result<()>;
+
+    /// Called once after the last message. Buffered output must be flushed
+    /// here so that write errors are reported instead of lost on drop.
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Files created so far, in the order they were created. Empty for
+    /// sinks that don't write files.
+    fn paths(&self) -> &[PathBuf] {
+        &[]
+    }
+
+    /// Flushes and closes the open files, if the sink can pick up where it
+    /// left off in them on a later message; [`PartitionedSink`] calls this
+    /// on idle partitions. Sinks that can't, such as Parquet files, which
+    /// are only complete once finished, keep them open.
+    fn close_files(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<S: OutputSink + ?Sized> OutputSink for &mut S {
+    fn on_message(&mut self, ctx: &Context, msg: &Message) -> io::Result<()> {
+        (**self).on_message(ctx, msg)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        (**self).finish()
+    }
+
+    fn paths(&self) -> &[PathBuf] {
+        (**self).paths()
+    }
+
+    fn close_files(&mut self) -> io::Result<()> {
+        (**self).close_files()
+    }
+}
+
+impl<S: OutputSink + ?Sized> OutputSink for Box<S> {
+    fn on_message(&mut self, ctx: &Context, msg: &Message) -> io::Result<()> {
+        (**self).on_message(ctx, msg)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        (**self).finish()
+    }
+
+    fn paths(&self) -> &[PathBuf] {
+        (**self).paths()
+    }
+
+    fn close_files(&mut self) -> io::Result<()> {
+        (**self).close_files()
+    }
+}
+
+/// Name of the output file for message type `t`, e.g. `S051018-v50-A.csv`
+/// for extension `csv`.
+pub fn output_file_name(base: &str, t: u8, extension: &str) -> String {
+    if t == b'h' {
+        // workaround of the limitation of case-insensitive filesystems
+        // both 'H' and 'h' are valid message types
+        format!("{}-halt.{}", base, extension)
+    } else {
+        format!("{}-{}.{}", base, t as char, extension)
+    }
+}
+
+/// An alpha field without its space padding.
+pub(crate) fn trim_alpha(v: &[u8]) -> &[u8] {
+    let end = v.iter().rposition(|&c| c != b' ').map_or(0, |i| i + 1);
+    &v[..end]
+}
+
+/// A buffered output file that remembers its path for error messages.
+pub(crate) struct Output {
+    pub(crate) path: PathBuf,
+    pub(crate) writer: BufWriter<File>,
+}
+
+impl Output {
+    pub(crate) fn create(path: PathBuf, buffer_size: usize) -> io::Result<Self> {
+        match File::create(&path) {
+            Ok(f) => Ok(Output { writer: BufWriter::with_capacity(buffer_size, f), path }),
+            Err(e) => Err(with_path(e, "can't create", &path)),
+        }
+    }
+
+    /// Opens `path` to write at its end, creating it if need be.
+    pub(crate) fn append(path: PathBuf, buffer_size: usize) -> io::Result<Self> {
+        match OpenOptions::new().append(true).create(true).open(&path) {
+            Ok(f) => Ok(Output { writer: BufWriter::with_capacity(buffer_size, f), path }),
+            Err(e) => Err(with_path(e, "can't open", &path)),
+        }
+    }
+
+    /// Adds the path to a write error.
+    pub(crate) fn error(&self, e: io::Error) -> io::Error {
+        with_path(e, "can't write to", &self.path)
+    }
+}
+
+pub(crate) fn with_path(e: io::Error, what: &str, path: &Path) -> io::Error {
+    io::Error::new(e.kind(), format!("{} {}: {}", what, path.display(), e))
+}
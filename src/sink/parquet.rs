@@ -0,0 +1,160 @@
+// (C) Copyright 2020 Shawfeng Dong. All rights reserved.
+// Use of this source code is governed by an MIT-style
+// license that can be found in the LICENSE file.
+
+//! One Parquet file per message type, with typed columns.
+
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::PathBuf;
+
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::file::properties::WriterProperties;
+
+use super::batch::{BatchBuilder, DEFAULT_BATCH_SIZE};
+use super::{output_file_name, Context, Output, OutputSink, DEFAULT_WRITE_BUFFER};
+use crate::message::*;
+
+/// Default number of rows in a Parquet row group.
+pub const DEFAULT_ROW_GROUP_SIZE: usize = 1024 * 1024;
+
+/// Writes each message type to its own Parquet file,
+/// `<base>-<type>.parquet` in the output folder, compressed with zstd.
+/// Files are created on the first message of their type.
+///
+/// Columns are named after the fields of the specification and typed:
+/// integers keep their width, timestamps are `UInt64` nanoseconds since
+/// midnight, prices are `Decimal128(10, 4)` or `Decimal128(20, 8)`, and
+/// alpha fields such as the stock symbol are dictionary-encoded strings
+/// with the padding trimmed.
+pub struct ParquetSink {
+    folder: PathBuf,
+    base: String,
+    row_group_size: usize,
+    buffer_size: usize,
+    files: Vec<Option<ParquetFile>>,
+    paths: Vec<PathBuf>,
+}
+
+struct ParquetFile {
+    path: PathBuf,
+    batch: BatchBuilder,
+    writer: ArrowWriter<BufWriter<File>>,
+}
+
+impl ParquetFile {
+    // Hands the collected rows to the writer.
+    fn write_batch(&mut self) -> io::Result<()> {
+        let batch = self.batch.finish();
+        self.writer.write(&batch).map_err(|e| self.error(e))
+    }
+
+    fn error(&self, e: parquet::errors::ParquetError) -> io::Error {
+        super::with_path(io::Error::other(e), "can't write to", &self.path)
+    }
+}
+
+impl ParquetSink {
+    pub fn new<P: Into<PathBuf>, S: Into<String>>(folder: P, base: S) -> Self {
+        ParquetSink {
+            folder: folder.into(),
+            base: base.into(),
+            row_group_size: DEFAULT_ROW_GROUP_SIZE,
+            buffer_size: DEFAULT_WRITE_BUFFER,
+            files: MESSAGE_TYPES.iter().map(|_| None).collect(),
+            paths: Vec::new(),
+        }
+    }
+
+    /// Sets the maximum number of rows in a row group.
+    pub fn row_group_size(mut self, rows: usize) -> Self {
+        self.row_group_size = rows;
+        self
+    }
+
+    /// Sets the size in bytes of the write buffer of each file.
+    pub fn buffer_size(mut self, bytes: usize) -> Self {
+        self.buffer_size = bytes;
+        self
+    }
+
+    /// Path of the Parquet file for message type `t`.
+    pub fn path_for(&self, t: u8) -> PathBuf {
+        self.folder.join(output_file_name(&self.base, t, "parquet"))
+    }
+
+    fn create(&self, t: u8) -> io::Result<ParquetFile> {
+        let Output { path, writer } = Output::create(self.path_for(t), self.buffer_size)?;
+        let batch = BatchBuilder::new(t).expect("decoded messages have a known type");
+        let props = WriterProperties::builder()
+            .set_compression(Compression::ZSTD(ZstdLevel::default()))
+            .set_max_row_group_size(self.row_group_size)
+            .build();
+        match ArrowWriter::try_new(writer, batch.schema(), Some(props)) {
+            Ok(writer) => Ok(ParquetFile { path, batch, writer }),
+            Err(e) => Err(super::with_path(io::Error::other(e), "can't create", &path)),
+        }
+    }
+}
+
+impl OutputSink for ParquetSink {
+    fn on_message(&mut self, _ctx: &Context, msg: &Message) -> io::Result<()> {
+        let t = msg.message_type();
+        let i = message_type_index(t).expect("decoded messages have a known type");
+        if self.files[i].is_none() {
+            let file = self.create(t)?;
+            self.paths.push(file.path.clone());
+            self.files[i] = Some(file);
+        }
+        let file = self.files[i].as_mut().unwrap();
+        file.batch.append(msg);
+        // the writer encodes each batch into the current row group
+        if file.batch.len() >= DEFAULT_BATCH_SIZE.min(self.row_group_size) {
+            file.write_batch()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        for file in self.files.iter_mut().flatten() {
+            if !file.batch.is_empty() {
+                file.write_batch()?;
+            }
+            file.writer.finish().map_err(|e| file.error(e))?;
+        }
+        Ok(())
+    }
+
+    fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    use super::*;
+    use crate::parser::Parser;
+    use crate::reader::SliceFrames;
+    use crate::testing::*;
+
+    #[test]
+    fn truncated_input_still_writes_the_footer() {
+        let folder = scratch_folder("parquet-truncated");
+        let mut input = feed(&[
+            add(1, 7, 1, b'B', 100, 1_000_000),
+            add(2, 7, 2, b'S', 50, 1_010_000),
+        ]);
+        input.truncate(input.len() - 5);
+        let mut sink = ParquetSink::new(&folder, "feed");
+        assert!(Parser::new().run(&mut SliceFrames::new(&input), &mut sink).is_err());
+        let file = File::open(sink.path_for(b'A')).unwrap();
+        let reader = SerializedFileReader::new(file).unwrap();
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 1);
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
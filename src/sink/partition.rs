@@ -0,0 +1,247 @@
+// (C) Copyright 2020 Shawfeng Dong. All rights reserved.
+// Use of this source code is governed by an MIT-style
+// license that can be found in the LICENSE file.
+
+//! Output partitioned by stock, one folder per partition.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::{with_path, Context, OutputSink};
+use crate::directory::Directory;
+use crate::message::{message_type_index, Message, MESSAGE_TYPES};
+
+/// Default number of buckets of [`Partitioning::LocateBucket`].
+pub const DEFAULT_BUCKETS: u16 = 32;
+
+/// Default number of partitions [`PartitionedSink`] keeps open at once. A
+/// partition has a file per message type of a stock, up to 19, so this
+/// keeps a CSV output under 1024 open files, a common limit.
+pub const DEFAULT_OPEN_PARTITIONS: usize = 32;
+
+/// Write buffer size that suits the many files of a partitioned output.
+pub const PARTITION_WRITE_BUFFER: usize = 16 * 1024;
+
+/// How [`PartitionedSink`] splits the messages into folders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Partitioning {
+    /// One folder per stock, named after its symbol, e.g. `AAPL`. Each
+    /// folder has files of its own, so a whole-market feed writes thousands
+    /// of them.
+    Symbol,
+    /// A fixed number of folders, `bucket-00` and up, each holding the
+    /// stocks whose locate codes are equal modulo the number of buckets.
+    LocateBucket(u16),
+}
+
+/// Splits the messages by stock and hands each partition to a sink of its
+/// own, created by `make` for the partition's folder under `folder` on its
+/// first message. Market-wide messages (stock locate 0) go to a sink for
+/// `folder` itself.
+///
+/// Symbols are learned from the Stock Directory (`R`) messages, which the
+/// sink must be given even if they are not to be written; see
+/// [`only`](Self::only). A stock without one is put in a folder named
+/// after its locate code, e.g. `locate-123`.
+///
+/// To stay within the limit on open files, at most
+/// [`max_open`](Self::max_open) partitions are open at once: on a message
+/// for another one, the sink of the partition used least recently is told
+/// to [`close_files`](OutputSink::close_files). A [`CsvSink`](super::CsvSink)
+/// closes its files and appends to them later on; a
+/// [`ParquetSink`](super::ParquetSink) can't, so its files all stay open
+/// until the end, along with up to a batch of rows each in memory, and the
+/// number of partitions is what caps them.
+pub struct PartitionedSink<S, F> {
+    folder: PathBuf,
+    partitioning: Partitioning,
+    make: F,
+    directory: Directory,
+    // indexed like MESSAGE_TYPES
+    selected: Vec<bool>,
+    // indexed by partition: stock locate or bucket
+    sinks: Vec<Option<S>>,
+    // when each partition was last used, 0 if its files are closed
+    used: Vec<u64>,
+    // open partitions by when they were last used
+    open: BTreeMap<u64, usize>,
+    max_open: usize,
+    clock: u64,
+    market: Option<S>,
+    paths: Vec<PathBuf>,
+}
+
+impl<S, F> PartitionedSink<S, F>
+where
+    S: OutputSink,
+    F: FnMut(&Path) -> S,
+{
+    pub fn new<P: Into<PathBuf>>(folder: P, partitioning: Partitioning, make: F) -> Self {
+        if let Partitioning::LocateBucket(buckets) = partitioning {
+            assert!(buckets > 0, "number of buckets must be positive");
+        }
+        PartitionedSink {
+            folder: folder.into(),
+            partitioning,
+            make,
+            directory: Directory::new(),
+            selected: vec![true; MESSAGE_TYPES.len()],
+            sinks: Vec::new(),
+            used: Vec::new(),
+            open: BTreeMap::new(),
+            max_open: DEFAULT_OPEN_PARTITIONS,
+            clock: 0,
+            market: None,
+            paths: Vec::new(),
+        }
+    }
+
+    /// Sets the number of partitions whose files are open at once, which
+    /// must be positive; [`DEFAULT_OPEN_PARTITIONS`] by default. The
+    /// market-wide sink doesn't count.
+    pub fn max_open(mut self, partitions: usize) -> Self {
+        assert!(partitions > 0, "at least one partition must be open");
+        self.max_open = partitions;
+        self
+    }
+
+    /// Only passes the message types in `types`, e.g. `b"AEX"`, on to the
+    /// partitions. Messages of other types are still used to learn the
+    /// symbols.
+    pub fn only(mut self, types: &[u8]) -> Self {
+        for (selected, t) in self.selected.iter_mut().zip(MESSAGE_TYPES) {
+            *selected = types.contains(&t);
+        }
+        self
+    }
+
+    /// Folder of the partition of `stock_locate`, which must not be 0.
+    pub fn folder_for(&self, stock_locate: u16) -> PathBuf {
+        let name = match self.partitioning {
+            Partitioning::Symbol => match self.directory.symbol(stock_locate) {
+                Some(symbol) => symbol.to_string(),
+                None => format!("locate-{}", stock_locate),
+            },
+            Partitioning::LocateBucket(buckets) => {
+                let width = (buckets - 1).to_string().len().max(2);
+                format!("bucket-{:0width$}", stock_locate % buckets, width = width)
+            }
+        };
+        self.folder.join(name)
+    }
+
+    fn partition(&self, stock_locate: u16) -> usize {
+        match self.partitioning {
+            Partitioning::Symbol => usize::from(stock_locate),
+            Partitioning::LocateBucket(buckets) => usize::from(stock_locate % buckets),
+        }
+    }
+
+    // Marks partition `i` as the last used, closing the files of the one
+    // used least recently if `i` was closed and too many are open.
+    fn touch(&mut self, i: usize) -> io::Result<()> {
+        if self.used[i] == self.clock && self.clock > 0 {
+            return Ok(());
+        }
+        if self.used[i] > 0 {
+            self.open.remove(&self.used[i]);
+        } else if self.open.len() >= self.max_open {
+            let (_, idle) = self.open.pop_first().unwrap();
+            self.used[idle] = 0;
+            self.sinks[idle].as_mut().unwrap().close_files()?;
+        }
+        self.clock += 1;
+        self.used[i] = self.clock;
+        self.open.insert(self.clock, i);
+        Ok(())
+    }
+}
+
+impl<S, F> OutputSink for PartitionedSink<S, F>
+where
+    S: OutputSink,
+    F: FnMut(&Path) -> S,
+{
+    fn on_message(&mut self, ctx: &Context, msg: &Message) -> io::Result<()> {
+        self.directory.apply(msg);
+        let t = msg.message_type();
+        if !self.selected[message_type_index(t).expect("decoded messages have a known type")] {
+            return Ok(());
+        }
+        let locate = msg.stock_locate();
+        let sink = if locate == 0 {
+            self.market.get_or_insert_with(|| (self.make)(&self.folder))
+        } else {
+            let i = self.partition(locate);
+            if i >= self.sinks.len() {
+                self.sinks.resize_with(i + 1, || None);
+                self.used.resize(i + 1, 0);
+            }
+            if self.sinks[i].is_none() {
+                let folder = self.folder_for(locate);
+                fs::create_dir_all(&folder).map_err(|e| with_path(e, "can't create", &folder))?;
+                self.sinks[i] = Some((self.make)(&folder));
+            }
+            self.touch(i)?;
+            self.sinks[i].as_mut().unwrap()
+        };
+        let created = sink.paths().len();
+        sink.on_message(ctx, msg)?;
+        self.paths.extend_from_slice(&sink.paths()[created..]);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        for sink in self.market.iter_mut().chain(self.sinks.iter_mut().flatten()) {
+            // sinks that write everything at the end create their files here
+            let created = sink.paths().len();
+            sink.finish()?;
+            self.paths.extend_from_slice(&sink.paths()[created..]);
+        }
+        Ok(())
+    }
+
+    fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::sink::CsvSink;
+    use crate::testing::*;
+
+    #[test]
+    fn idle_partitions_are_closed_and_appended_to() {
+        let folder = scratch_folder("partition-reopen");
+        let make = |folder: &Path| CsvSink::new(folder, "feed");
+        let mut sink = PartitionedSink::new(&folder, Partitioning::Symbol, make).max_open(1);
+        let ctx = Context { seq: 0, offset: 0 };
+        let msgs = [
+            directory(1, 1, "AAPL"),
+            directory(1, 2, "MSFT"),
+            add(2, 1, 1, b'B', 100, 1_000_000),
+            add(3, 2, 2, b'B', 100, 2_000_000),
+            add(4, 1, 3, b'S', 100, 1_010_000),
+            add(5, 2, 4, b'S', 100, 2_010_000),
+        ];
+        for msg in &msgs {
+            sink.on_message(&ctx, msg).unwrap();
+        }
+        sink.finish().unwrap();
+        let rows = |symbol: &str| {
+            let csv = fs::read_to_string(folder.join(symbol).join("feed-A.csv")).unwrap();
+            csv.lines().map(|l| l.split(',').nth(4).unwrap().to_string()).collect::<Vec<_>>()
+        };
+        assert_eq!(rows("AAPL"), ["order_reference_number", "1", "3"]);
+        assert_eq!(rows("MSFT"), ["order_reference_number", "2", "4"]);
+        // each file is reported once
+        assert_eq!(sink.paths().len(), 4);
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
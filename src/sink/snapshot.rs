@@ -0,0 +1,254 @@
+// (C) Copyright 2020 Shawfeng Dong. All rights reserved.
+// Use of this source code is governed by an MIT-style
+// license that can be found in the LICENSE file.
+
+//! Depth (L2) snapshots of the order book.
+
+use std::io;
+use std::path::PathBuf;
+
+use super::table::{Cell, ColumnType, Table, TableFormat};
+use super::{Context, OutputSink};
+use crate::book::{Book, Level, OrderBook, Side};
+use crate::directory::Directory;
+use crate::message::Message;
+
+/// Default number of price levels per side in a snapshot.
+pub const DEFAULT_DEPTH: usize = 5;
+
+/// When [`SnapshotSink`] takes snapshots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    /// Every so many nanoseconds of ITCH time, on the multiples of the
+    /// interval since midnight. Each snapshot reflects all messages up to
+    /// and including its timestamp.
+    Interval(u64),
+    /// After every message that changes a book, timestamped with that
+    /// message.
+    OnChange,
+}
+
+/// Rebuilds the order book and writes the top price levels of each side,
+/// one wide row per stock and snapshot:
+///
+/// ```text
+/// timestamp,stock_locate,stock,bid_px_1,...,bid_px_N,bid_sz_1,...,bid_sz_N,ask_px_1,...,ask_sz_N
+/// ```
+///
+/// Levels beyond the depth of the book are left empty. On an interval,
+/// stocks with an empty book are left out.
+///
+/// The sink needs the order messages (`A`, `F`, `E`, `C`, `X`, `D` and
+/// `U`) and, for the symbols, the Stock Directory messages (`R`). To
+/// snapshot a few stocks only, filter the messages with
+/// [`Parser::symbols`](crate::Parser::symbols).
+pub struct SnapshotSink {
+    table: Table,
+    depth: usize,
+    trigger: Trigger,
+    book: OrderBook,
+    directory: Directory,
+    next_tick: Option<u64>,
+}
+
+impl SnapshotSink {
+    /// A sink writing to `path` in `format`, with [`DEFAULT_DEPTH`] levels
+    /// every 100 ms.
+    pub fn new<P: Into<PathBuf>>(path: P, format: TableFormat) -> Self {
+        SnapshotSink {
+            table: Table::new(path.into(), format, columns(DEFAULT_DEPTH)),
+            depth: DEFAULT_DEPTH,
+            trigger: Trigger::Interval(100_000_000),
+            book: OrderBook::new(),
+            directory: Directory::new(),
+            next_tick: None,
+        }
+    }
+
+    /// Sets the number of price levels per side.
+    pub fn depth(mut self, levels: usize) -> Self {
+        self.depth = levels;
+        self.table.set_columns(columns(levels));
+        self
+    }
+
+    /// Sets when to take snapshots. An interval must be positive.
+    pub fn trigger(mut self, trigger: Trigger) -> Self {
+        if let Trigger::Interval(nanos) = trigger {
+            assert!(nanos > 0, "snapshot interval must be positive");
+        }
+        self.trigger = trigger;
+        self
+    }
+
+    /// Only writes the snapshots timestamped at or after `start` and before
+    /// `end`; see [time windows](super#time-windows).
+    pub fn window(mut self, start: u64, end: u64) -> Self {
+        self.table.set_window(start, end);
+        self
+    }
+
+    /// The order book as of the last message.
+    pub fn order_book(&self) -> &OrderBook {
+        &self.book
+    }
+
+    pub fn directory(&self) -> &Directory {
+        &self.directory
+    }
+
+    // Writes one snapshot of the book of `stock_locate`.
+    fn write(&mut self, timestamp: u64, stock_locate: u16) -> io::Result<()> {
+        let empty = Book::default();
+        let book = self.book.book(stock_locate).unwrap_or(&empty);
+        let symbol = self.directory.symbol(stock_locate);
+        let cells = row(timestamp, stock_locate, symbol, book, self.depth);
+        self.table.write_row(&cells)
+    }
+
+    // Writes a snapshot of every stock with orders.
+    fn write_all(&mut self, timestamp: u64) -> io::Result<()> {
+        // don't build the rows the table would drop
+        if !self.table.in_window(timestamp) {
+            return Ok(());
+        }
+        let locates: Vec<u16> = self.book.books().map(|(locate, _)| locate).collect();
+        for locate in locates {
+            self.write(timestamp, locate)?;
+        }
+        Ok(())
+    }
+}
+
+impl OutputSink for SnapshotSink {
+    fn on_message(&mut self, _ctx: &Context, msg: &Message) -> io::Result<()> {
+        let timestamp = msg.timestamp();
+        if let Trigger::Interval(interval) = self.trigger {
+            let mut tick = self.next_tick.unwrap_or(timestamp.div_ceil(interval) * interval);
+            while tick < timestamp {
+                self.write_all(tick)?;
+                tick += interval;
+            }
+            self.next_tick = Some(tick);
+        }
+
+        self.directory.apply(msg);
+        if self.book.apply(msg) && self.trigger == Trigger::OnChange {
+            self.write(timestamp, msg.stock_locate())?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        // the last interval, up to the end of the input
+        if let (Trigger::Interval(_), Some(tick)) = (self.trigger, self.next_tick) {
+            self.write_all(tick)?;
+        }
+        self.table.finish()
+    }
+
+    fn paths(&self) -> &[PathBuf] {
+        self.table.paths()
+    }
+}
+
+fn columns(depth: usize) -> Vec<(String, ColumnType)> {
+    let mut columns = vec![
+        ("timestamp".to_string(), ColumnType::Timestamp),
+        ("stock_locate".to_string(), ColumnType::U16),
+        ("stock".to_string(), ColumnType::Symbol),
+    ];
+    for side in ["bid", "ask"] {
+        for i in 1..=depth {
+            columns.push((format!("{}_px_{}", side, i), ColumnType::Price4));
+        }
+        for i in 1..=depth {
+            columns.push((format!("{}_sz_{}", side, i), ColumnType::U64));
+        }
+    }
+    columns
+}
+
+fn row<'a>(
+    timestamp: u64,
+    stock_locate: u16,
+    symbol: Option<&'a str>,
+    book: &Book,
+    depth: usize,
+) -> Vec<Cell<'a>> {
+    let mut cells = Vec::with_capacity(3 + 4 * depth);
+    cells.push(Cell::Timestamp(timestamp));
+    cells.push(Cell::U16(stock_locate));
+    cells.push(symbol.map_or(Cell::Null, Cell::Symbol));
+    for side in [Side::Buy, Side::Sell] {
+        let mut levels: Vec<Option<Level>> = book.levels(side).take(depth).map(Some).collect();
+        levels.resize(depth, None);
+        cells.extend(levels.iter().map(|l| l.map_or(Cell::Null, |l| Cell::Price4(l.price))));
+        cells.extend(levels.iter().map(|l| l.map_or(Cell::Null, |l| Cell::U64(l.shares))));
+    }
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::testing::*;
+
+    // The data rows written one level deep on `trigger`, given `msgs`.
+    fn snapshots(name: &str, trigger: Trigger, msgs: &[Message]) -> Vec<String> {
+        let folder = scratch_folder(name);
+        let path = folder.join("book.csv");
+        let mut sink = SnapshotSink::new(&path, TableFormat::Csv).depth(1).trigger(trigger);
+        let ctx = Context { seq: 0, offset: 0 };
+        for msg in msgs {
+            sink.on_message(&ctx, msg).unwrap();
+        }
+        sink.finish().unwrap();
+        let csv = fs::read_to_string(&path).unwrap();
+        fs::remove_dir_all(&folder).unwrap();
+        csv.lines().skip(1).map(String::from).collect()
+    }
+
+    #[test]
+    fn interval() {
+        let msgs = [
+            add(5, 7, 1, b'B', 100, 1_000_000),
+            // on a tick, so in its snapshot
+            add(10, 7, 2, b'S', 30, 1_010_000),
+            add(12, 7, 3, b'B', 10, 1_000_000),
+            add(31, 7, 4, b'S', 20, 1_005_000),
+        ];
+        assert_eq!(
+            snapshots("snapshot-interval", Trigger::Interval(10), &msgs),
+            [
+                // the first tick is the first multiple at or after the first message
+                "0.000000010,7,,100.0000,100,101.0000,30",
+                "0.000000020,7,,100.0000,110,101.0000,30",
+                "0.000000030,7,,100.0000,110,101.0000,30",
+                // the last tick is written by finish
+                "0.000000040,7,,100.0000,110,100.5000,20",
+            ]
+        );
+    }
+
+    #[test]
+    fn on_change() {
+        let msgs = [
+            add(5, 7, 1, b'B', 100, 1_000_000),
+            // unknown order, no change
+            delete(6, 7, 9),
+            add(8, 7, 2, b'S', 30, 1_010_000),
+            delete(9, 7, 1),
+        ];
+        assert_eq!(
+            snapshots("snapshot-on-change", Trigger::OnChange, &msgs),
+            [
+                "0.000000005,7,,100.0000,100,,",
+                "0.000000008,7,,100.0000,100,101.0000,30",
+                "0.000000009,7,,,,101.0000,30",
+            ]
+        );
+    }
+}
@@ -0,0 +1,239 @@
+// (C) Copyright 2020 Shawfeng Dong. All rights reserved.
+// Use of this source code is governed by an MIT-style
+// license that can be found in the LICENSE file.
+
+//! A SQLite database with one table per message type.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use rusqlite::Connection;
+
+use super::{trim_alpha, with_path, Context, OutputSink};
+use crate::message::*;
+
+// Columns that get an index, in the tables that have them, along with
+// the original and new order reference numbers of `U`.
+const INDEXED: [&str; 3] = ["stock_locate", "timestamp", "order_reference_number"];
+
+fn is_indexed(column: &str) -> bool {
+    INDEXED.iter().any(|&name| column == name || column.ends_with(&format!("_{}", name)))
+}
+
+/// Writes the messages to a SQLite database, one table per message type,
+/// named after the type in snake case with a `_message` suffix, e.g.
+/// `add_order_message`. Tables are created on the first message of their
+/// type.
+///
+/// Every table starts with `seq`, the index of the frame in the input (see
+/// [`Context`]), as its primary key, so that rows of different tables can
+/// be put back in feed order. The other columns are named after the fields
+/// of the specification: integers and timestamps (nanoseconds since
+/// midnight) are `INTEGER`, and characters and alpha fields are `TEXT`,
+/// the latter without the space padding. Prices are exact `INTEGER` fixed
+/// point, as on the wire: `Price(4)` in units of 1/10,000 and `Price(8)`
+/// in units of 1/100,000,000, e.g. `1009300` for 100.93 in `add_order_message`.
+///
+/// Everything is loaded in one transaction, which
+/// [`finish`](OutputSink::finish) commits. [`Parser::run`](crate::Parser::run)
+/// finishes the sink even if the input is cut short, so the database then
+/// holds the messages before the error.
+///
+/// [`finish`](OutputSink::finish) also indexes `stock_locate`, `timestamp`
+/// and the order reference numbers, including
+/// `original_order_reference_number` and `new_order_reference_number` of
+/// `U`, and creates a `stock_directory` view that maps locate codes to
+/// symbols when there are `R` messages.
+pub struct SqliteSink {
+    path: PathBuf,
+    conn: Connection,
+    // insert statement of each message type whose table exists
+    inserts: Vec<Option<String>>,
+    paths: Vec<PathBuf>,
+}
+
+impl SqliteSink {
+    /// Creates the database at `path`, replacing any existing file.
+    pub fn create<P: Into<PathBuf>>(path: P) -> io::Result<Self> {
+        let path = path.into();
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => {
+                return Err(with_path(e, "can't replace", &path))
+            }
+            _ => {}
+        }
+        let error = |e| with_path(io::Error::other(e), "can't create", &path);
+        let conn = Connection::open(&path).map_err(error)?;
+        // load everything in one transaction, with the journal in memory:
+        // without one, a rollback would leave a corrupt file behind
+        conn.execute_batch("PRAGMA journal_mode = MEMORY; PRAGMA synchronous = OFF; BEGIN")
+            .map_err(error)?;
+        conn.set_prepared_statement_cache_capacity(MESSAGE_TYPES.len());
+        Ok(SqliteSink {
+            paths: vec![path.clone()],
+            path,
+            conn,
+            inserts: MESSAGE_TYPES.iter().map(|_| None).collect(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn error(&self, e: rusqlite::Error) -> io::Error {
+        with_path(io::Error::other(e), "can't write to", &self.path)
+    }
+
+    // Creates the table of message type `t` and returns its insert
+    // statement.
+    fn create_table(&self, t: u8) -> rusqlite::Result<String> {
+        let table = table_name(t).expect("decoded messages have a known type");
+        let fields = message_fields(t).unwrap();
+        let mut create = format!("CREATE TABLE {} (seq INTEGER PRIMARY KEY", table);
+        let mut insert = format!("INSERT INTO {} VALUES (?", table);
+        for field in fields {
+            let column_type = match field.kind {
+                FieldKind::Char | FieldKind::Alpha(_) => "TEXT",
+                _ => "INTEGER",
+            };
+            write!(create, ", {} {} NOT NULL", field.name, column_type).unwrap();
+            insert.push_str(", ?");
+        }
+        create.push(')');
+        insert.push(')');
+        self.conn.execute_batch(&create)?;
+        Ok(insert)
+    }
+
+    fn insert(&self, sql: &str, ctx: &Context, msg: &Message) -> rusqlite::Result<()> {
+        let mut stmt = self.conn.prepare_cached(sql)?;
+        stmt.raw_bind_parameter(1, ctx.seq as i64)?;
+        let mut i = 2;
+        let mut result = Ok(());
+        msg.for_each_field(|_, value| {
+            if result.is_err() {
+                return;
+            }
+            // u64 fields above i64::MAX wrap around; ITCH never sends them
+            result = match value {
+                Value::Char(c) => stmt.raw_bind_parameter(i, char::from(c).to_string()),
+                Value::U16(v) => stmt.raw_bind_parameter(i, v),
+                Value::U32(v) | Value::Price4(v) => stmt.raw_bind_parameter(i, v),
+                Value::U64(v) | Value::Timestamp(v) | Value::Price8(v) => {
+                    stmt.raw_bind_parameter(i, v as i64)
+                }
+                Value::Alpha(v) => {
+                    stmt.raw_bind_parameter(i, String::from_utf8_lossy(trim_alpha(v)))
+                }
+            };
+            i += 1;
+        });
+        result?;
+        stmt.raw_execute()?;
+        Ok(())
+    }
+
+    // Indexes the tables and creates the views, then commits.
+    fn finish_database(&self) -> rusqlite::Result<()> {
+        let mut sql = String::new();
+        for (&t, insert) in MESSAGE_TYPES.iter().zip(&self.inserts) {
+            if insert.is_none() {
+                continue;
+            }
+            let table = table_name(t).unwrap();
+            for field in message_fields(t).unwrap() {
+                if is_indexed(field.name) {
+                    writeln!(sql, "CREATE INDEX {0}_{1} ON {0} ({1});", table, field.name)
+                        .unwrap();
+                }
+            }
+        }
+        if self.inserts[message_type_index(b'R').unwrap()].is_some() {
+            sql.push_str(
+                "CREATE VIEW stock_directory AS \
+                 SELECT stock_locate, stock, timestamp, market_category, round_lot_size, \
+                 issue_classification, issue_sub_type \
+                 FROM stock_directory_message;",
+            );
+        }
+        sql.push_str("COMMIT;");
+        self.conn.execute_batch(&sql)
+    }
+}
+
+impl OutputSink for SqliteSink {
+    fn on_message(&mut self, ctx: &Context, msg: &Message) -> io::Result<()> {
+        let t = msg.message_type();
+        let i = message_type_index(t).expect("decoded messages have a known type");
+        if self.inserts[i].is_none() {
+            self.inserts[i] = Some(self.create_table(t).map_err(|e| self.error(e))?);
+        }
+        let sql = self.inserts[i].as_deref().unwrap();
+        self.insert(sql, ctx, msg).map_err(|e| self.error(e))
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.finish_database().map_err(|e| {
+            // leave the database as it was created rather than half indexed
+            if !self.conn.is_autocommit() {
+                let _ = self.conn.execute_batch("ROLLBACK");
+            }
+            self.error(e)
+        })
+    }
+
+    fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+}
+
+/// Name of the table of message type `t`, e.g. `add_order_message` for
+/// `A`, or `None` for an unknown type.
+pub fn table_name(t: u8) -> Option<String> {
+    let mut table = String::new();
+    for c in message_name(t)?.chars() {
+        if c.is_ascii_uppercase() && !table.is_empty() {
+            table.push('_');
+        }
+        table.push(c.to_ascii_lowercase());
+    }
+    table.push_str("_message");
+    Some(table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::reader::SliceFrames;
+    use crate::testing::*;
+
+    #[test]
+    fn truncated_input_is_committed() {
+        let folder = scratch_folder("sqlite-truncated");
+        let mut input = feed(&[
+            add(1, 7, 1, b'B', 100, 1_000_000),
+            add(2, 7, 2, b'S', 50, 1_010_000),
+        ]);
+        input.truncate(input.len() - 5);
+        let path = folder.join("feed.db");
+        let mut sink = SqliteSink::create(&path).unwrap();
+        assert!(Parser::new().run(&mut SliceFrames::new(&input), &mut sink).is_err());
+        drop(sink);
+        let conn = Connection::open(&path).unwrap();
+        let sql = "SELECT order_reference_number, price FROM add_order_message";
+        let rows: Vec<(i64, i64)> = conn
+            .prepare(sql)
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(rows, [(1, 1_000_000)]);
+        drop(conn);
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
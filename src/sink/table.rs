@@ -0,0 +1,277 @@
+// (C) Copyright 2020 Shawfeng Dong. All rights reserved.
+// Use of this source code is governed by an MIT-style
+// license that can be found in the LICENSE file.
+
+//! Row-by-row writing of derived tables, such as book snapshots, to CSV or
+//! Parquet.
+
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use arrow_array::RecordBatch;
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::file::properties::WriterProperties;
+
+use super::batch::{Column, DEFAULT_BATCH_SIZE};
+use super::{with_path, Output, DEFAULT_WRITE_BUFFER};
+use crate::message::{Price4, Timestamp};
+
+/// File format of a derived table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableFormat {
+    /// CSV with a header row. Timestamps and prices are written as in the
+    /// per-type CSV files and missing values are left empty.
+    Csv,
+    /// Parquet, compressed with zstd. Timestamps are `UInt64` nanoseconds
+    /// since midnight, prices `Decimal128(10, 4)` and symbols are
+    /// dictionary encoded.
+    Parquet,
+}
+
+impl TableFormat {
+    /// File name extension, without the dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            TableFormat::Csv => "csv",
+            TableFormat::Parquet => "parquet",
+        }
+    }
+}
+
+/// Type of a column of a derived table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ColumnType {
+    U16,
+    U64,
+    Timestamp,
+    Price4,
+    F64,
+    Char,
+    /// Short, repetitive text such as a stock symbol.
+    Symbol,
+    Bool,
+}
+
+impl ColumnType {
+    fn data_type(self) -> DataType {
+        match self {
+            ColumnType::U16 => DataType::UInt16,
+            ColumnType::U64 | ColumnType::Timestamp => DataType::UInt64,
+            ColumnType::Price4 => DataType::Decimal128(10, 4),
+            ColumnType::F64 => DataType::Float64,
+            ColumnType::Char => DataType::Utf8,
+            ColumnType::Symbol => {
+                DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8))
+            }
+            ColumnType::Bool => DataType::Boolean,
+        }
+    }
+}
+
+/// One value of a row. Each must match the type of its column.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Cell<'a> {
+    Null,
+    U16(u16),
+    U64(u64),
+    Timestamp(u64),
+    Price4(u32),
+    F64(f64),
+    Char(u8),
+    Symbol(&'a str),
+    Bool(bool),
+}
+
+/// A derived table written one row at a time. The file is created on the
+/// first row.
+///
+/// The first column is the timestamp of the row, which rows outside the
+/// [time window](super#time-windows) are dropped on.
+pub(crate) struct Table {
+    path: PathBuf,
+    format: TableFormat,
+    columns: Vec<(String, ColumnType)>,
+    window: Range<u64>,
+    file: Option<TableFile>,
+}
+
+enum TableFile {
+    Csv { out: Output, line: String },
+    Parquet {
+        path: PathBuf,
+        schema: SchemaRef,
+        columns: Vec<Column>,
+        rows: usize,
+        writer: Box<ArrowWriter<BufWriter<File>>>,
+    },
+}
+
+impl Table {
+    pub(crate) fn new(
+        path: PathBuf,
+        format: TableFormat,
+        columns: Vec<(String, ColumnType)>,
+    ) -> Self {
+        debug_assert_eq!(columns.first().map(|c| c.1), Some(ColumnType::Timestamp));
+        Table { path, format, columns, window: 0..u64::MAX, file: None }
+    }
+
+    /// Replaces the columns, before any row is written.
+    pub(crate) fn set_columns(&mut self, columns: Vec<(String, ColumnType)>) {
+        assert!(self.file.is_none(), "columns are set before writing");
+        self.columns = columns;
+    }
+
+    /// Only writes the rows timestamped at or after `start` and before
+    /// `end`.
+    pub(crate) fn set_window(&mut self, start: u64, end: u64) {
+        self.window = start..end;
+    }
+
+    /// Whether a row timestamped `timestamp` would be written.
+    pub(crate) fn in_window(&self, timestamp: u64) -> bool {
+        self.window.contains(&timestamp)
+    }
+
+    /// The file, once created.
+    pub(crate) fn paths(&self) -> &[PathBuf] {
+        match self.file {
+            Some(_) => std::slice::from_ref(&self.path),
+            None => &[],
+        }
+    }
+
+    /// Writes one row, unless its timestamp is outside the window.
+    pub(crate) fn write_row(&mut self, cells: &[Cell<'_>]) -> io::Result<()> {
+        debug_assert_eq!(cells.len(), self.columns.len());
+        match cells.first() {
+            Some(&Cell::Timestamp(t)) if !self.in_window(t) => return Ok(()),
+            _ => {}
+        }
+        if self.file.is_none() {
+            self.file = Some(self.create()?);
+        }
+        match self.file.as_mut().unwrap() {
+            TableFile::Csv { out, line } => {
+                line.clear();
+                for (i, cell) in cells.iter().enumerate() {
+                    if i > 0 {
+                        line.push(',');
+                    }
+                    write_csv_cell(line, cell);
+                }
+                line.push('\n');
+                out.writer.write_all(line.as_bytes()).map_err(|e| out.error(e))
+            }
+            TableFile::Parquet { path, schema, columns, rows, writer } => {
+                for (column, cell) in columns.iter_mut().zip(cells) {
+                    append(column, cell);
+                }
+                *rows += 1;
+                if *rows >= DEFAULT_BATCH_SIZE {
+                    *rows = 0;
+                    write_batch(writer, schema, columns).map_err(|e| parquet_error(e, path))?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Flushes the file, if any rows were written.
+    pub(crate) fn finish(&mut self) -> io::Result<()> {
+        match &mut self.file {
+            None => Ok(()),
+            Some(TableFile::Csv { out, .. }) => out.writer.flush().map_err(|e| out.error(e)),
+            Some(TableFile::Parquet { path, schema, columns, rows, writer }) => {
+                if *rows > 0 {
+                    *rows = 0;
+                    write_batch(writer, schema, columns).map_err(|e| parquet_error(e, path))?;
+                }
+                writer.finish().map(|_| ()).map_err(|e| parquet_error(e, path))
+            }
+        }
+    }
+
+    fn create(&self) -> io::Result<TableFile> {
+        let mut out = Output::create(self.path.clone(), DEFAULT_WRITE_BUFFER)?;
+        match self.format {
+            TableFormat::Csv => {
+                let names: Vec<&str> = self.columns.iter().map(|(name, _)| name.as_str()).collect();
+                writeln!(out.writer, "{}", names.join(",")).map_err(|e| out.error(e))?;
+                Ok(TableFile::Csv { out, line: String::new() })
+            }
+            TableFormat::Parquet => {
+                let schema = Arc::new(Schema::new(
+                    self.columns
+                        .iter()
+                        .map(|(name, ty)| Field::new(name, ty.data_type(), true))
+                        .collect::<Vec<_>>(),
+                ));
+                let props = WriterProperties::builder()
+                    .set_compression(Compression::ZSTD(ZstdLevel::default()))
+                    .build();
+                let columns = schema.fields().iter().map(|f| Column::new(f.data_type())).collect();
+                let Output { path, writer } = out;
+                match ArrowWriter::try_new(writer, schema.clone(), Some(props)) {
+                    Ok(writer) => Ok(TableFile::Parquet {
+                        columns,
+                        path,
+                        schema,
+                        rows: 0,
+                        writer: Box::new(writer),
+                    }),
+                    Err(e) => Err(with_path(io::Error::other(e), "can't create", &path)),
+                }
+            }
+        }
+    }
+}
+
+fn write_csv_cell(line: &mut String, cell: &Cell<'_>) {
+    match *cell {
+        Cell::Null => {}
+        Cell::U16(v) => write!(line, "{}", v).unwrap(),
+        Cell::U64(v) => write!(line, "{}", v).unwrap(),
+        Cell::Timestamp(v) => write!(line, "{}", Timestamp(v)).unwrap(),
+        Cell::Price4(v) => write!(line, "{}", Price4(v)).unwrap(),
+        Cell::F64(v) => write!(line, "{}", v).unwrap(),
+        Cell::Char(c) => line.push(char::from(c)),
+        Cell::Symbol(s) => line.push_str(s),
+        Cell::Bool(b) => line.push_str(if b { "true" } else { "false" }),
+    }
+}
+
+fn write_batch(
+    writer: &mut ArrowWriter<BufWriter<File>>,
+    schema: &SchemaRef,
+    columns: &mut [Column],
+) -> parquet::errors::Result<()> {
+    let columns = columns.iter_mut().map(Column::finish).collect();
+    let batch = RecordBatch::try_new(schema.clone(), columns).expect("columns match the schema");
+    writer.write(&batch)
+}
+
+fn parquet_error(e: parquet::errors::ParquetError, path: &Path) -> io::Error {
+    with_path(io::Error::other(e), "can't write to", path)
+}
+
+// Appends `cell` to the column built for its column type.
+fn append(column: &mut Column, cell: &Cell<'_>) {
+    match (column, *cell) {
+        (Column::U16(b), Cell::U16(v)) => b.append_value(v),
+        (Column::U64(b), Cell::U64(v) | Cell::Timestamp(v)) => b.append_value(v),
+        (Column::Decimal(b), Cell::Price4(v)) => b.append_value(i128::from(v)),
+        (Column::F64(b), Cell::F64(v)) => b.append_value(v),
+        (Column::Text(b), Cell::Char(c)) => b.append_value(char::from(c).encode_utf8(&mut [0; 4])),
+        (Column::Dictionary(b), Cell::Symbol(s)) => b.append_value(s),
+        (Column::Bool(b), Cell::Bool(v)) => b.append_value(v),
+        (column, Cell::Null) => column.append_null(),
+        _ => unreachable!("cell and column types match"),
+    }
+}
@@ -0,0 +1,203 @@
+// (C) Copyright 2020 Shawfeng Dong. All rights reserved.
+// Use of this source code is governed by an MIT-style
+// license that can be found in the LICENSE file.
+
+//! The trade tape as a table.
+
+use std::collections::HashSet;
+use std::io;
+use std::path::PathBuf;
+
+use super::table::{Cell, ColumnType, Table, TableFormat};
+use super::{Context, OutputSink};
+use crate::directory::Directory;
+use crate::message::Message;
+use crate::tape::{Print, TradeTape};
+
+/// Writes one row per print of the [`TradeTape`]:
+///
+/// ```text
+/// timestamp,stock_locate,stock,message_type,match_number,price,shares,aggressor_side,printable,cross_type,broken
+/// ```
+///
+/// `aggressor_side` is `B` or `S` where it can be inferred, i.e. for `E`
+/// and `C` on orders in the book (see [`Print::aggressor`]), `cross_type`
+/// is only set for `Q`, and `broken` is `true` for prints that a Broken
+/// Trade (`B`) message broke later in the day. Since a trade can be broken at any
+/// time, the rows are kept in memory and written at the end of the input.
+///
+/// The sink needs `E`, `C`, `P`, `Q` and `B`, the other order messages
+/// (`A`, `F`, `X`, `D` and `U`) to price `E`, and the Stock Directory
+/// messages (`R`) for the symbols.
+pub struct TradesSink {
+    table: Table,
+    log: PrintLog,
+}
+
+impl TradesSink {
+    pub fn new<P: Into<PathBuf>>(path: P, format: TableFormat) -> Self {
+        let columns = [
+            ("timestamp", ColumnType::Timestamp),
+            ("stock_locate", ColumnType::U16),
+            ("stock", ColumnType::Symbol),
+            ("message_type", ColumnType::Char),
+            ("match_number", ColumnType::U64),
+            ("price", ColumnType::Price4),
+            ("shares", ColumnType::U64),
+            ("aggressor_side", ColumnType::Char),
+            ("printable", ColumnType::Bool),
+            ("cross_type", ColumnType::Char),
+            ("broken", ColumnType::Bool),
+        ];
+        let columns = columns.iter().map(|&(name, ty)| (name.to_string(), ty)).collect();
+        TradesSink { table: Table::new(path.into(), format, columns), log: PrintLog::new() }
+    }
+
+    /// Only writes the prints timestamped at or after `start` and before
+    /// `end`; see [time windows](super#time-windows).
+    pub fn window(mut self, start: u64, end: u64) -> Self {
+        self.table.set_window(start, end);
+        self
+    }
+
+    /// Prints so far, in feed order.
+    pub fn prints(&self) -> &[Print] {
+        &self.log.prints
+    }
+
+    /// Whether the trade with `match_number` has been broken.
+    pub fn is_broken(&self, match_number: u64) -> bool {
+        self.log.is_broken(match_number)
+    }
+}
+
+/// The prints of the [`TradeTape`] kept until the end of the input, along
+/// with the symbols and the match numbers of the trades broken so far.
+pub(crate) struct PrintLog {
+    tape: TradeTape,
+    pub(crate) directory: Directory,
+    pub(crate) prints: Vec<Print>,
+    broken: HashSet<u64>,
+}
+
+impl PrintLog {
+    pub(crate) fn new() -> Self {
+        PrintLog {
+            tape: TradeTape::new(),
+            directory: Directory::new(),
+            prints: Vec::new(),
+            broken: HashSet::new(),
+        }
+    }
+
+    pub(crate) fn apply(&mut self, msg: &Message) {
+        self.directory.apply(msg);
+        if let Message::BrokenTrade(m) = msg {
+            self.broken.insert(m.match_number);
+        }
+        self.prints.extend(self.tape.apply(msg));
+    }
+
+    pub(crate) fn is_broken(&self, match_number: u64) -> bool {
+        self.broken.contains(&match_number)
+    }
+}
+
+impl OutputSink for TradesSink {
+    fn on_message(&mut self, _ctx: &Context, msg: &Message) -> io::Result<()> {
+        self.log.apply(msg);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let log = &self.log;
+        for p in &log.prints {
+            let cells = [
+                Cell::Timestamp(p.timestamp),
+                Cell::U16(p.stock_locate),
+                log.directory.symbol(p.stock_locate).map_or(Cell::Null, Cell::Symbol),
+                Cell::Char(p.message_type),
+                Cell::U64(p.match_number),
+                p.price.map_or(Cell::Null, Cell::Price4),
+                Cell::U64(p.shares),
+                p.aggressor.map_or(Cell::Null, |side| Cell::Char(side.indicator())),
+                Cell::Bool(p.printable),
+                p.cross_type.map_or(Cell::Null, Cell::Char),
+                Cell::Bool(log.is_broken(p.match_number)),
+            ];
+            self.table.write_row(&cells)?;
+        }
+        self.table.finish()
+    }
+
+    fn paths(&self) -> &[PathBuf] {
+        self.table.paths()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::testing::*;
+
+    #[test]
+    fn broken_trades_are_marked() {
+        let folder = scratch_folder("trades-broken");
+        let path = folder.join("trades.csv");
+        let mut sink = TradesSink::new(&path, TableFormat::Csv);
+        let ctx = Context { seq: 0, offset: 0 };
+        let msgs = [
+            add(1, 7, 1, b'S', 100, 1_000_000),
+            execute(2, 7, 1, 30, 11),
+            execute(3, 7, 1, 20, 12),
+            // breaks the first print, after the second
+            broken(4, 7, 11),
+        ];
+        for msg in &msgs {
+            sink.on_message(&ctx, msg).unwrap();
+        }
+        sink.finish().unwrap();
+        assert!(sink.is_broken(11));
+        assert!(!sink.is_broken(12));
+        let csv = fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            csv.lines().skip(1).collect::<Vec<_>>(),
+            [
+                "0.000000002,7,,E,11,100.0000,30,B,true,,true",
+                "0.000000003,7,,E,12,100.0000,20,B,true,,false",
+            ]
+        );
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn window() {
+        let folder = scratch_folder("trades-window");
+        let path = folder.join("trades.csv");
+        let mut sink = TradesSink::new(&path, TableFormat::Csv).window(3, 5);
+        let ctx = Context { seq: 0, offset: 0 };
+        let msgs = [
+            add(1, 7, 1, b'S', 100, 1_000_000),
+            execute(2, 7, 1, 10, 11),
+            execute(3, 7, 1, 10, 12),
+            execute(4, 7, 1, 10, 13),
+            execute(5, 7, 1, 10, 14),
+            broken(6, 7, 13),
+        ];
+        for msg in &msgs {
+            sink.on_message(&ctx, msg).unwrap();
+        }
+        sink.finish().unwrap();
+        let csv = fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            csv.lines().skip(1).collect::<Vec<_>>(),
+            [
+                "0.000000003,7,,E,12,100.0000,10,B,true,,false",
+                "0.000000004,7,,E,13,100.0000,10,B,true,,true",
+            ]
+        );
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
@@ -0,0 +1,177 @@
+// (C) Copyright 2020 Shawfeng Dong. All rights reserved.
+// Use of this source code is governed by an MIT-style
+// license that can be found in the LICENSE file.
+
+//! The trade tape: one print per execution, cross or non-displayed trade.
+//!
+//! - `E` executes a resting order at its own price, which comes from the
+//!   order book,
+//! - `C` executes a resting order at another price, and may be
+//!   non-printable, e.g. the executions that make up a cross,
+//! - `P` is a trade against a non-displayed order, whose side is not
+//!   disclosed,
+//! - `Q` is the print of an opening, closing, halt or IPO cross.
+//!
+//! Broken Trade (`B`) messages refer back to a print by its match number.
+
+use crate::book::{OrderBook, Side};
+use crate::message::Message;
+
+/// One print.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Print {
+    /// Nanoseconds since midnight.
+    pub timestamp: u64,
+    pub stock_locate: u16,
+    /// The message it came from: `E`, `C`, `P` or `Q`.
+    pub message_type: u8,
+    pub match_number: u64,
+    /// `Price(4)`; `None` for an `E` on an order that was added before the
+    /// start of the input.
+    pub price: Option<u32>,
+    pub shares: u64,
+    /// Side of the incoming order, the opposite of the resting order it
+    /// matched; `None` for crosses, unknown orders and `P`, whose buy/sell
+    /// indicator has been `B` regardless of the resting side since 2014.
+    pub aggressor: Option<Side>,
+    /// Whether the print counts towards volume; only `C` can be
+    /// non-printable.
+    pub printable: bool,
+    /// Cross type of a `Q`, e.g. `O` for the opening cross.
+    pub cross_type: Option<u8>,
+}
+
+/// Turns the message stream into [`Print`]s, joining executions to their
+/// orders through an [`OrderBook`] it keeps up to date.
+#[derive(Default)]
+pub struct TradeTape {
+    book: OrderBook,
+}
+
+impl TradeTape {
+    pub fn new() -> Self {
+        TradeTape::default()
+    }
+
+    /// Applies one message. Returns the print of `E`, `C`, `P` and `Q`
+    /// messages, and `None` for anything else.
+    pub fn apply(&mut self, msg: &Message) -> Option<Print> {
+        let print = self.print(msg);
+        self.book.apply(msg);
+        print
+    }
+
+    /// The order book as of the last message.
+    pub fn order_book(&self) -> &OrderBook {
+        &self.book
+    }
+
+    // The print of `msg`, looked up before the execution is applied.
+    fn print(&self, msg: &Message) -> Option<Print> {
+        let base = Print {
+            timestamp: msg.timestamp(),
+            stock_locate: msg.stock_locate(),
+            message_type: msg.message_type(),
+            match_number: 0,
+            price: None,
+            shares: 0,
+            aggressor: None,
+            printable: true,
+            cross_type: None,
+        };
+        match msg {
+            Message::OrderExecuted(m) => {
+                let order = self.book.order(m.order_reference_number);
+                Some(Print {
+                    match_number: m.match_number,
+                    price: order.map(|o| o.price),
+                    shares: u64::from(m.executed_shares),
+                    aggressor: order.map(|o| o.side.opposite()),
+                    ..base
+                })
+            }
+            Message::OrderExecutedWithPrice(m) => {
+                let order = self.book.order(m.order_reference_number);
+                Some(Print {
+                    match_number: m.match_number,
+                    price: Some(m.execution_price),
+                    shares: u64::from(m.executed_shares),
+                    aggressor: order.map(|o| o.side.opposite()),
+                    printable: m.printable == b'Y',
+                    ..base
+                })
+            }
+            Message::Trade(m) => Some(Print {
+                match_number: m.match_number,
+                price: Some(m.price),
+                shares: u64::from(m.shares),
+                ..base
+            }),
+            Message::CrossTrade(m) => Some(Print {
+                match_number: m.match_number,
+                price: Some(m.cross_price),
+                shares: m.shares,
+                cross_type: Some(m.cross_type),
+                ..base
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+
+    #[test]
+    fn executions_take_the_opposite_side_of_the_resting_order() {
+        let mut tape = TradeTape::new();
+        assert_eq!(tape.apply(&add(1, 7, 1, b'B', 100, 1_000_000)), None);
+        tape.apply(&add(2, 7, 2, b'S', 100, 1_010_000));
+        let print = tape.apply(&execute(3, 7, 1, 40, 11)).unwrap();
+        assert_eq!(
+            print,
+            Print {
+                timestamp: 3,
+                stock_locate: 7,
+                message_type: b'E',
+                match_number: 11,
+                price: Some(1_000_000),
+                shares: 40,
+                aggressor: Some(Side::Sell),
+                printable: true,
+                cross_type: None,
+            }
+        );
+        let print = tape.apply(&execute_at(4, 7, 2, 10, 12, b'Y', 1_005_000)).unwrap();
+        assert_eq!(print.price, Some(1_005_000));
+        assert_eq!(print.aggressor, Some(Side::Buy));
+        assert!(print.printable);
+        // the order is gone, so neither price nor side is known
+        tape.apply(&delete(5, 7, 1));
+        let print = tape.apply(&execute(6, 7, 1, 10, 13)).unwrap();
+        assert_eq!((print.price, print.aggressor), (None, None));
+    }
+
+    #[test]
+    fn non_printable_executions() {
+        let mut tape = TradeTape::new();
+        tape.apply(&add(1, 7, 1, b'S', 100, 1_000_000));
+        let print = tape.apply(&execute_at(2, 7, 1, 100, 11, b'N', 1_000_000)).unwrap();
+        assert!(!print.printable);
+        assert_eq!(print.aggressor, Some(Side::Buy));
+    }
+
+    #[test]
+    fn trades_and_crosses_have_no_aggressor() {
+        let mut tape = TradeTape::new();
+        let print = tape.apply(&trade(1, 7, 200, 1_000_000, 11)).unwrap();
+        assert_eq!((print.price, print.shares, print.aggressor), (Some(1_000_000), 200, None));
+        assert_eq!(print.cross_type, None);
+        let print = tape.apply(&cross(2, 7, 5_000, 1_010_000, 12, b'O')).unwrap();
+        assert_eq!((print.price, print.shares, print.aggressor), (Some(1_010_000), 5_000, None));
+        assert_eq!(print.cross_type, Some(b'O'));
+        assert!(print.printable);
+    }
+}
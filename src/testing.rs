@@ -0,0 +1,221 @@
+// (C) Copyright 2020 Shawfeng Dong. All rights reserved.
+// Use of this source code is governed by an MIT-style
+// license that can be found in the LICENSE file.
+
+//! Messages, feeds and scratch folders for the unit tests.
+
+use std::fs;
+use std::path::PathBuf;
+
+use crate::message::*;
+
+/// `symbol` as a space-padded alpha field.
+pub(crate) fn stock(symbol: &str) -> [u8; 8] {
+    let mut stock = [b' '; 8];
+    stock[..symbol.len()].copy_from_slice(symbol.as_bytes());
+    stock
+}
+
+/// `msgs` encoded as a feed: each message behind its 2-byte length.
+pub(crate) fn feed(msgs: &[Message]) -> Vec<u8> {
+    let mut feed = Vec::new();
+    for msg in msgs {
+        let mut body = vec![msg.message_type()];
+        msg.for_each_field(|_, value| match value {
+            Value::Char(c) => body.push(c),
+            Value::U16(v) => body.extend(v.to_be_bytes()),
+            Value::U32(v) | Value::Price4(v) => body.extend(v.to_be_bytes()),
+            Value::U64(v) | Value::Price8(v) => body.extend(v.to_be_bytes()),
+            Value::Timestamp(v) => body.extend(&v.to_be_bytes()[2..]),
+            Value::Alpha(v) => body.extend(v),
+        });
+        feed.extend((body.len() as u16).to_be_bytes());
+        feed.extend(body);
+    }
+    feed
+}
+
+/// An empty folder of its own under the system's temporary folder.
+pub(crate) fn scratch_folder(name: &str) -> PathBuf {
+    let folder = std::env::temp_dir().join(format!("itch5-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&folder);
+    fs::create_dir_all(&folder).unwrap();
+    folder
+}
+
+pub(crate) fn add(
+    timestamp: u64,
+    locate: u16,
+    reference: u64,
+    indicator: u8,
+    shares: u32,
+    price: u32,
+) -> Message {
+    Message::AddOrder(AddOrder {
+        stock_locate: locate,
+        tracking_number: 0,
+        timestamp,
+        order_reference_number: reference,
+        buy_sell_indicator: indicator,
+        shares,
+        stock: stock("AAPL"),
+        price,
+    })
+}
+
+pub(crate) fn delete(timestamp: u64, locate: u16, reference: u64) -> Message {
+    Message::OrderDelete(OrderDelete {
+        stock_locate: locate,
+        tracking_number: 0,
+        timestamp,
+        order_reference_number: reference,
+    })
+}
+
+pub(crate) fn execute(
+    timestamp: u64,
+    locate: u16,
+    reference: u64,
+    shares: u32,
+    match_number: u64,
+) -> Message {
+    Message::OrderExecuted(OrderExecuted {
+        stock_locate: locate,
+        tracking_number: 0,
+        timestamp,
+        order_reference_number: reference,
+        executed_shares: shares,
+        match_number,
+    })
+}
+
+pub(crate) fn execute_at(
+    timestamp: u64,
+    locate: u16,
+    reference: u64,
+    shares: u32,
+    match_number: u64,
+    printable: u8,
+    price: u32,
+) -> Message {
+    Message::OrderExecutedWithPrice(OrderExecutedWithPrice {
+        stock_locate: locate,
+        tracking_number: 0,
+        timestamp,
+        order_reference_number: reference,
+        executed_shares: shares,
+        match_number,
+        printable,
+        execution_price: price,
+    })
+}
+
+/// A `P`, with the buy/sell indicator `B` it has had since 2014.
+pub(crate) fn trade(
+    timestamp: u64,
+    locate: u16,
+    shares: u32,
+    price: u32,
+    match_number: u64,
+) -> Message {
+    Message::Trade(Trade {
+        stock_locate: locate,
+        tracking_number: 0,
+        timestamp,
+        order_reference_number: 0,
+        buy_sell_indicator: b'B',
+        shares,
+        stock: stock("AAPL"),
+        price,
+        match_number,
+    })
+}
+
+pub(crate) fn cross(
+    timestamp: u64,
+    locate: u16,
+    shares: u64,
+    price: u32,
+    match_number: u64,
+    cross_type: u8,
+) -> Message {
+    Message::CrossTrade(CrossTrade {
+        stock_locate: locate,
+        tracking_number: 0,
+        timestamp,
+        shares,
+        stock: stock("AAPL"),
+        cross_price: price,
+        match_number,
+        cross_type,
+    })
+}
+
+pub(crate) fn broken(timestamp: u64, locate: u16, match_number: u64) -> Message {
+    Message::BrokenTrade(BrokenTrade {
+        stock_locate: locate,
+        tracking_number: 0,
+        timestamp,
+        match_number,
+    })
+}
+
+pub(crate) fn system_event(timestamp: u64, event_code: u8) -> Message {
+    Message::SystemEvent(SystemEvent {
+        stock_locate: 0,
+        tracking_number: 0,
+        timestamp,
+        event_code,
+    })
+}
+
+pub(crate) fn directory(timestamp: u64, locate: u16, symbol: &str) -> Message {
+    Message::StockDirectory(StockDirectory {
+        stock_locate: locate,
+        tracking_number: 0,
+        timestamp,
+        stock: stock(symbol),
+        market_category: b'Q',
+        financial_status_indicator: b'N',
+        round_lot_size: 100,
+        round_lots_only: b'N',
+        issue_classification: b'C',
+        issue_sub_type: *b"Z ",
+        authenticity: b'P',
+        short_sale_threshold_indicator: b'N',
+        ipo_flag: b'N',
+        luld_reference_price_tier: b'1',
+        etp_flag: b'N',
+        etp_leverage_factor: 0,
+        inverse_indicator: b'N',
+    })
+}
+
+pub(crate) fn cancel(timestamp: u64, locate: u16, reference: u64, shares: u32) -> Message {
+    Message::OrderCancel(OrderCancel {
+        stock_locate: locate,
+        tracking_number: 0,
+        timestamp,
+        order_reference_number: reference,
+        cancelled_shares: shares,
+    })
+}
+
+pub(crate) fn replace(
+    timestamp: u64,
+    locate: u16,
+    original: u64,
+    new: u64,
+    shares: u32,
+    price: u32,
+) -> Message {
+    Message::OrderReplace(OrderReplace {
+        stock_locate: locate,
+        tracking_number: 0,
+        timestamp,
+        original_order_reference_number: original,
+        new_order_reference_number: new,
+        shares,
+        price,
+    })
+}